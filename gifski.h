@@ -62,11 +62,11 @@ it will build `target/aarch64-apple-ios/release/libgifski.a` (ignore the warning
  */
 typedef struct GifskiSettings {
   /**
-   * Resize to max this width if non-0.
+   * Resize to max this width if non-0. See also `gifski_set_resize`.
    */
   uint32_t width;
   /**
-   * Resize to max this height if width is non-0. Note that aspect ratio is not preserved.
+   * Resize to max this height if non-0. Aspect ratio is preserved, unless `width` is non-0 too.
    */
   uint32_t height;
   /**
@@ -136,11 +136,36 @@ gifski *gifski_new(const GifskiSettings *settings);
 
 /** Quality 1-100 of temporal denoising. Lower values reduce motion. Defaults to `settings.quality`.
  *
+ * Returns `GIFSKI_INVALID_INPUT` if the quality is out of range. Versions before 2.0 accepted any value.
  * Only valid immediately after calling `gifski_new`, before any frames are added. */
 GifskiError gifski_set_motion_quality(gifski *handle, uint8_t quality);
 
+enum GifskiResizeMode {
+  /** Downscale to about 800×600 */
+  GIFSKI_RESIZE_AUTO = 0,
+  /** Keep the original size */
+  GIFSKI_RESIZE_NONE = 1,
+  /** Fit within width×height (0 means no limit), without upscaling */
+  GIFSKI_RESIZE_FIT = 2,
+  /** Cover width×height and crop the rest */
+  GIFSKI_RESIZE_FILL = 3,
+  /** Stretch to width×height */
+  GIFSKI_RESIZE_EXACT = 4,
+  /** Multiply both dimensions by `scale` */
+  GIFSKI_RESIZE_SCALE = 5,
+  /** Downscale to have at most width×height pixels, keeping the aspect ratio */
+  GIFSKI_RESIZE_MAX_PIXELS = 6,
+};
+
+/** Overrides `width`/`height` of `GifskiSettings`. `mode` is one of `GifskiResizeMode` values.
+ * `scale` is used only by `GIFSKI_RESIZE_SCALE`.
+ *
+ * Only valid immediately after calling `gifski_new`, before any frames are added. */
+GifskiError gifski_set_resize(gifski *handle, int mode, uint32_t width, uint32_t height, float scale);
+
 /** Quality 1-100 of gifsicle compression. Lower values add noise. Defaults to `settings.quality`.
  * Has no effect if the `gifsicle` feature hasn't been enabled.
+ * Returns `GIFSKI_INVALID_INPUT` if the quality is out of range. Versions before 2.0 accepted any value.
  * Only valid immediately after calling `gifski_new`, before any frames are added. */
 GifskiError gifski_set_lossy_quality(gifski *handle, uint8_t quality);
 
@@ -149,6 +174,11 @@ GifskiError gifski_set_lossy_quality(gifski *handle, uint8_t quality);
  * Only valid immediately after calling `gifski_new`, before any frames are added. */
 GifskiError gifski_set_extra_effort(gifski *handle, bool extra);
 
+/** Semitransparent pixels will be blended with this background color, instead of having their transparency dithered.
+ *
+ * Only valid immediately after calling `gifski_new`, before any frames are added. */
+GifskiError gifski_set_matte_color(gifski *handle, uint8_t col_r, uint8_t col_g, uint8_t col_b);
+
 /**
  * Adds a fixed color that will be kept in the palette at all times.
  *
@@ -179,6 +209,20 @@ GifskiError gifski_add_frame_png_file(gifski *handle,
                                       const char *file_path,
                                       double presentation_timestamp);
 
+/**
+ * Same as `gifski_add_frame_png_file`, but instead of a timestamp, it takes time in seconds for how long this frame is to be displayed.
+ * The frame starts when the previous frame ends.
+ *
+ * The duration is rounded to whole centiseconds, and used exactly, including for the last frame.
+ * Durations shorter than 0.01s are made 0.01s, and GIF frames shorter than 0.02s are lengthened.
+ *
+ * Use either durations or timestamps for all frames, not both.
+ */
+GifskiError gifski_add_frame_png_file_with_duration(gifski *handle,
+                                      uint32_t frame_number,
+                                      const char *file_path,
+                                      double duration);
+
 /**
  * Insert a new frame at the position of `frame_number`.
  *
@@ -221,6 +265,23 @@ GifskiError gifski_add_frame_rgba_stride(gifski *handle,
                                   const unsigned char *pixels,
                                   double presentation_timestamp);
 
+/**
+ * Same as `gifski_add_frame_rgba_stride`, but instead of a timestamp, it takes time in seconds for how long this frame is to be displayed.
+ * The frame starts when the previous frame ends.
+ *
+ * The duration is rounded to whole centiseconds, and used exactly, including for the last frame.
+ * Durations shorter than 0.01s are made 0.01s, and GIF frames shorter than 0.02s are lengthened.
+ *
+ * Use either durations or timestamps for all frames, not both.
+ */
+GifskiError gifski_add_frame_rgba_stride_with_duration(gifski *handle,
+                                  uint32_t frame_number,
+                                  uint32_t width,
+                                  uint32_t height,
+                                  uint32_t bytes_per_row,
+                                  const unsigned char *pixels,
+                                  double duration);
+
 /** Same as `gifski_add_frame_rgba_stride`, except it expects components in ARGB order.
 
 Bytes per row must be multiple of 4, and greater or equal width×4.
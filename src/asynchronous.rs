@@ -0,0 +1,198 @@
+//! Async versions of [`Collector`] and [`Writer`]. Requires the `async` feature.
+//!
+//! The encoding still happens on gifski's own threads, but adding frames and writing the output
+//! don't block the async executor. When the encoder is busy, adding frames waits asynchronously.
+//!
+//! The output is written to [`futures::io::AsyncWrite`]. For tokio's `AsyncWrite`, use the `tokio-util` compat wrapper.
+
+use crate::collector::{Collector, FrameSource, FrameTime, ImgVec, InputFrame, RGBA8};
+use crate::error::GifResult;
+use crate::progress::ProgressReporter;
+use crate::{Error, Settings, Writer};
+use futures::channel::{mpsc, oneshot};
+use futures::executor::{block_on, block_on_stream};
+use futures::io::{AsyncWrite, AsyncWriteExt};
+use futures::{SinkExt, StreamExt};
+use std::io;
+use std::thread;
+
+#[cfg(feature = "png")]
+use std::path::PathBuf;
+
+/// Async equivalent of [`gifski::new()`][crate::new]
+///
+/// Add frames with [`AsyncCollector`], and at the same time await [`AsyncWriter::write`],
+/// e.g. in `futures::join!` or in separate tasks.
+///
+/// ```rust,no_run
+/// # async fn example() -> Result<(), gifski::Error> {
+/// let (mut collector, writer) = gifski::asynchronous::new(gifski::Settings::default())?;
+/// let frames = async move {
+///     for i in 0..10 {
+///         collector.add_frame_png_file(i, format!("frame{i:04}.png").into(), i as f64 * 0.1).await?;
+///     }
+///     Ok::<_, gifski::Error>(())
+/// };
+/// let mut out = Vec::new();
+/// let (frames_res, write_res) = futures::join!(frames, writer.write(&mut out, gifski::progress::NoProgress {}));
+/// frames_res?;
+/// write_res?;
+/// # Ok(()) }
+/// ```
+pub fn new(settings: Settings) -> GifResult<(AsyncCollector, AsyncWriter)> {
+    let (collector, writer) = crate::new(settings)?;
+    let (queue, queue_iter) = mpsc::channel(1);
+    Ok((AsyncCollector { queue }, AsyncWriter { collector, writer, queue_iter }))
+}
+
+/// Collect frames that will be encoded. See [`Collector`].
+///
+/// Writing will finish only when the collector is dropped.
+pub struct AsyncCollector {
+    queue: mpsc::Sender<InputFrame>,
+}
+
+impl AsyncCollector {
+    /// Frame index starts at 0. Waits if the encoder is busy.
+    ///
+    /// See [`Collector::add_frame_rgba`].
+    pub async fn add_frame_rgba(&mut self, frame_index: usize, frame: ImgVec<RGBA8>, presentation_timestamp: f64) -> GifResult<()> {
+        debug_assert!(frame_index == 0 || presentation_timestamp > 0.);
+        self.send(InputFrame {
+            frame_index,
+            frame: FrameSource::Pixels(frame),
+            time: FrameTime::Pts(presentation_timestamp),
+        }).await
+    }
+
+    /// Frame index starts at 0. Waits if the encoder is busy.
+    ///
+    /// See [`Collector::add_frame_rgba_with_duration`].
+    pub async fn add_frame_rgba_with_duration(&mut self, frame_index: usize, frame: ImgVec<RGBA8>, duration: f64) -> GifResult<()> {
+        debug_assert!(duration > 0.);
+        self.send(InputFrame {
+            frame_index,
+            frame: FrameSource::Pixels(frame),
+            time: FrameTime::Duration(duration),
+        }).await
+    }
+
+    /// Decode a frame from in-memory PNG-compressed data. Waits if the encoder is busy.
+    ///
+    /// See [`Collector::add_frame_png_data`].
+    #[cfg(feature = "png")]
+    pub async fn add_frame_png_data(&mut self, frame_index: usize, png_data: Vec<u8>, presentation_timestamp: f64) -> GifResult<()> {
+        self.send(InputFrame {
+            frame: FrameSource::PngData(png_data),
+            time: FrameTime::Pts(presentation_timestamp),
+            frame_index,
+        }).await
+    }
+
+    /// Read and decode a PNG file from disk. Waits if the encoder is busy.
+    ///
+    /// The file is read on the encoder's thread. See [`Collector::add_frame_png_file`].
+    #[cfg(all(feature = "png", not(target_arch = "wasm32")))]
+    pub async fn add_frame_png_file(&mut self, frame_index: usize, path: PathBuf, presentation_timestamp: f64) -> GifResult<()> {
+        self.send(InputFrame {
+            frame: FrameSource::Path(path),
+            time: FrameTime::Pts(presentation_timestamp),
+            frame_index,
+        }).await
+    }
+
+    /// Decode a frame from in-memory PNG-compressed data. Waits if the encoder is busy.
+    ///
+    /// See [`Collector::add_frame_png_data_with_duration`].
+    #[cfg(feature = "png")]
+    pub async fn add_frame_png_data_with_duration(&mut self, frame_index: usize, png_data: Vec<u8>, duration: f64) -> GifResult<()> {
+        debug_assert!(duration > 0.);
+        self.send(InputFrame {
+            frame: FrameSource::PngData(png_data),
+            time: FrameTime::Duration(duration),
+            frame_index,
+        }).await
+    }
+
+    /// Read and decode a PNG file from disk. Waits if the encoder is busy.
+    ///
+    /// The file is read on the encoder's thread. See [`Collector::add_frame_png_file_with_duration`].
+    #[cfg(all(feature = "png", not(target_arch = "wasm32")))]
+    pub async fn add_frame_png_file_with_duration(&mut self, frame_index: usize, path: PathBuf, duration: f64) -> GifResult<()> {
+        debug_assert!(duration > 0.);
+        self.send(InputFrame {
+            frame: FrameSource::Path(path),
+            time: FrameTime::Duration(duration),
+            frame_index,
+        }).await
+    }
+
+    async fn send(&mut self, frame: InputFrame) -> GifResult<()> {
+        self.queue.send(frame).await.map_err(|_| Error::ThreadSend)
+    }
+}
+
+/// Perform GIF writing. See [`Writer`].
+pub struct AsyncWriter {
+    collector: Collector,
+    writer: Writer,
+    queue_iter: mpsc::Receiver<InputFrame>,
+}
+
+/// Passes the writer's output to the async side, waiting when the sink is slow
+struct ChunkSender(mpsc::Sender<Vec<u8>>);
+
+impl io::Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            block_on(self.0.send(buf.to_vec())).map_err(|_| io::ErrorKind::BrokenPipe)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWriter {
+    /// Adds a fixed color that will be kept in the palette at all times.
+    ///
+    /// See [`Writer::add_fixed_color`].
+    #[inline]
+    pub fn add_fixed_color(&mut self, col: crate::collector::RGB8) {
+        self.writer.add_fixed_color(col);
+    }
+
+    /// Start writing frames. The future will not complete until the [`AsyncCollector`] is dropped.
+    ///
+    /// The output is flushed, but not closed.
+    ///
+    /// `ProgressReporter` is called from the encoder's thread.
+    pub async fn write<W: AsyncWrite + Unpin>(self, mut outfile: W, mut reporter: impl ProgressReporter + 'static) -> GifResult<()> {
+        let Self { collector, writer, queue_iter } = self;
+
+        // Not joined, because it may be waiting for a frame when the writer fails.
+        // It will exit when either side of the queue is closed.
+        thread::Builder::new().name("async-frames".into()).spawn(move || {
+            for frame in block_on_stream(queue_iter) {
+                if collector.queue.send(frame).is_err() {
+                    break;
+                }
+            }
+        })?;
+
+        let (chunks, mut chunks_recv) = mpsc::channel(4);
+        let (done, done_recv) = oneshot::channel();
+        thread::Builder::new().name("async-write".into()).spawn(move || {
+            let _ = done.send(writer.write(ChunkSender(chunks), &mut reporter));
+        })?;
+
+        // if this fails, dropping the receiver will abort the writer
+        while let Some(chunk) = chunks_recv.next().await {
+            outfile.write_all(&chunk).await?;
+        }
+        outfile.flush().await?;
+        done_recv.await.map_err(|_| Error::ThreadSend)?
+    }
+}
@@ -0,0 +1,178 @@
+//! This is for reading animated PNG as an input
+
+use crate::source::{Fps, Source, DEFAULT_FPS};
+use crate::{BinResult, SrcPath};
+use apng::{BlendOp, ColorType, DisposeOp, Transformations};
+use gifski::Collector;
+use imgref::{ImgRefMut, ImgVec};
+use rgb::RGBA8;
+use std::io::{BufRead, Read};
+
+pub struct ApngDecoder {
+    fps: Option<f32>,
+    speed: f32,
+    reader: apng::Reader<Box<dyn Read>>,
+    num_frames: u32,
+}
+
+/// Regular PNG files are treated as frames, so APNG needs to be recognized by its acTL chunk
+pub fn is_animated(src: &mut SrcPath) -> BinResult<bool> {
+    let mut file_tmp = Vec::new();
+    let header = match src {
+        SrcPath::Path(path) => {
+            std::fs::File::open(path)?.take(1 << 16).read_to_end(&mut file_tmp)?;
+            &file_tmp[..]
+        },
+        // don't consume
+        SrcPath::Stdin(stdin) => stdin.fill_buf()?,
+    };
+    // acTL must be before the image data
+    let mut chunks = header.get(8..).unwrap_or_default();
+    while chunks.len() >= 8 {
+        let len = u32::from_be_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]) as usize;
+        match &chunks[4..8] {
+            b"acTL" => return Ok(true),
+            b"IDAT" => break,
+            _ => {},
+        }
+        chunks = chunks.get(12 + len..).unwrap_or_default();
+    }
+    Ok(false)
+}
+
+impl ApngDecoder {
+    pub fn new(src: SrcPath, fps: Fps) -> BinResult<Self> {
+        let input = match src {
+            SrcPath::Path(path) => Box::new(std::io::BufReader::new(std::fs::File::open(path)?)) as Box<dyn Read>,
+            SrcPath::Stdin(buf) => Box::new(buf),
+        };
+
+        let mut decoder = apng::Decoder::new(input);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let reader = decoder.read_info()?;
+        let num_frames = reader.info().animation_control.ok_or("The PNG file is not animated")?.num_frames;
+
+        Ok(Self {
+            fps: fps.fps,
+            speed: fps.speed,
+            reader,
+            num_frames,
+        })
+    }
+}
+
+impl Source for ApngDecoder {
+    fn total_frames(&self) -> Option<u64> {
+        if self.fps.is_some() { None } else { Some(self.num_frames.into()) }
+    }
+
+    fn collect(&mut self, c: &mut Collector) -> BinResult<()> {
+        let (width, height) = self.reader.info().size();
+        let (width, height) = (width as usize, height as usize);
+        let mut canvas = ImgVec::new(vec![RGBA8::default(); width * height], width, height);
+        let mut buf = vec![0; self.reader.output_buffer_size()];
+
+        // without fcTL before IDAT, the first image is only for non-animated viewers
+        let default_image_is_frame = self.reader.info().frame_control.is_some();
+        if !default_image_is_frame {
+            self.reader.next_frame(&mut buf)?;
+        }
+
+        let mut idx = 0;
+        let mut delay_ts = 0.;
+        let skip_frames = self.fps.is_some();
+        let wanted_frame_time = 1. / f64::from(self.fps.unwrap_or(DEFAULT_FPS));
+        let mut wanted_presentation_timestamp = 0.;
+        for n in 0..self.num_frames {
+            let fctl = if n == 0 && default_image_is_frame {
+                self.reader.info().frame_control.ok_or("missing fcTL")?
+            } else {
+                *self.reader.next_frame_info()?
+            };
+            let info = self.reader.next_frame(&mut buf)?;
+            let frame = to_rgba(&buf[..info.buffer_size()], info.color_type)?;
+            let (left, top) = (fctl.x_offset as usize, fctl.y_offset as usize);
+            let (frame_width, frame_height) = (fctl.width as usize, fctl.height as usize);
+            if left + frame_width > width || top + frame_height > height || frame.len() != frame_width * frame_height {
+                return Err(format!("APNG frame {n} is out of bounds").into());
+            }
+
+            let previous = if fctl.dispose_op == DisposeOp::Previous && n > 0 {
+                Some(canvas.sub_image(left, top, frame_width, frame_height).to_contiguous_buf().0.into_owned())
+            } else {
+                None
+            };
+            blit(canvas.sub_image_mut(left, top, frame_width, frame_height), &frame, fctl.blend_op);
+
+            let presentation_timestamp = delay_ts / f64::from(self.speed);
+            // denominator 0 means 1/100s
+            delay_ts += f64::from(fctl.delay_num) / f64::from(if fctl.delay_den == 0 { 100 } else { fctl.delay_den });
+            if !skip_frames || presentation_timestamp >= wanted_presentation_timestamp {
+                wanted_presentation_timestamp += wanted_frame_time;
+                c.add_frame_rgba(idx, canvas.clone(), presentation_timestamp)?;
+                idx += 1;
+            }
+
+            let area = canvas.sub_image_mut(left, top, frame_width, frame_height);
+            match (fctl.dispose_op, previous) {
+                (DisposeOp::None, _) => {},
+                (DisposeOp::Previous, Some(previous)) => blit(area, &previous, BlendOp::Source),
+                // the first frame disposes to background when it says previous
+                (DisposeOp::Background | DisposeOp::Previous, _) => {
+                    let transparent = vec![RGBA8::default(); frame_width * frame_height];
+                    blit(area, &transparent, BlendOp::Source);
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+fn blit(mut area: ImgRefMut<RGBA8>, frame: &[RGBA8], blend_op: BlendOp) {
+    let width = area.width();
+    for (row, frame_row) in area.rows_mut().zip(frame.chunks_exact(width)) {
+        for (dst, &src) in row.iter_mut().zip(frame_row) {
+            *dst = match blend_op {
+                BlendOp::Source => src,
+                BlendOp::Over => blend_over(*dst, src),
+            };
+        }
+    }
+}
+
+fn blend_over(dst: RGBA8, src: RGBA8) -> RGBA8 {
+    if src.a == 255 || dst.a == 0 {
+        return src;
+    }
+    if src.a == 0 {
+        return dst;
+    }
+    let src_a = u32::from(src.a);
+    let dst_a = u32::from(dst.a) * (255 - src_a) / 255;
+    let out_a = src_a + dst_a;
+    let mix = |s: u8, d: u8| ((u32::from(s) * src_a + u32::from(d) * dst_a) / out_a) as u8;
+    RGBA8::new(mix(src.r, dst.r), mix(src.g, dst.g), mix(src.b, dst.b), out_a as u8)
+}
+
+/// Expects 8-bit output of `normalize_to_color8`
+fn to_rgba(data: &[u8], color_type: ColorType) -> BinResult<Vec<RGBA8>> {
+    Ok(match color_type {
+        ColorType::Rgba => data.chunks_exact(4).map(|c| RGBA8::new(c[0], c[1], c[2], c[3])).collect(),
+        ColorType::Rgb => data.chunks_exact(3).map(|c| RGBA8::new(c[0], c[1], c[2], 255)).collect(),
+        ColorType::GrayscaleAlpha => data.chunks_exact(2).map(|c| RGBA8::new(c[0], c[0], c[0], c[1])).collect(),
+        ColorType::Grayscale => data.iter().map(|&g| RGBA8::new(g, g, g, 255)).collect(),
+        ColorType::Indexed => return Err("unexpected palette in APNG frame".into()),
+    })
+}
+
+#[test]
+fn blending() {
+    let red = RGBA8::new(255, 0, 0, 255);
+    let half_blue = RGBA8::new(0, 0, 255, 128);
+    assert_eq!(red, blend_over(half_blue, red));
+    assert_eq!(half_blue, blend_over(RGBA8::default(), half_blue));
+    assert_eq!(red, blend_over(red, RGBA8::new(0, 0, 0, 0)));
+    let mixed = blend_over(red, half_blue);
+    assert_eq!(255, mixed.a);
+    assert!(mixed.r > 100 && mixed.b > 100);
+}
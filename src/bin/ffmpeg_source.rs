@@ -1,6 +1,6 @@
 use crate::source::{Fps, Source};
 use crate::{BinResult, SrcPath};
-use gifski::{Collector, Settings};
+use gifski::{Collector, Crop, Settings};
 use imgref::*;
 use rgb::*;
 
@@ -48,11 +48,19 @@ impl FfmpegDecoder {
             codec_context.set_parameters(stream.parameters())?;
             let decoder = codec_context.decoder().video().map_err(|e| format!("Unable to decode the codec used in the video: {}", e))?;
 
-            let (dest_width, dest_height) = self.settings.dimensions_for_image(decoder.width() as _, decoder.height() as _);
+            let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+            // Resizing is left to gifski, but cropping early saves converting pixels that are going to be thrown away
+            let (left, top, crop_width, crop_height) = self.settings.crop_for_image(width, height);
+            // the crop of Resize::Fill is relative to frames already cropped with Settings::crop
+            let crop = if self.settings.crop == Crop::None && (crop_width, crop_height) != (width, height) {
+                format!("crop={crop_width}:{crop_height}:{left}:{top},")
+            } else {
+                String::new()
+            };
 
             let buffer_args = format!("width={}:height={}:video_size={}x{}:pix_fmt={}:time_base={}:sar={}",
-                dest_width,
-                dest_height,
+                decoder.width(),
+                decoder.height(),
                 decoder.width(),
                 decoder.height(),
                 decoder.format().descriptor().ok_or("ffmpeg format error")?.name(),
@@ -65,7 +73,7 @@ impl FfmpegDecoder {
             let mut filter = ffmpeg::filter::Graph::new();
             filter.add(&ffmpeg::filter::find("buffer").ok_or("ffmpeg format error")?, "in", &buffer_args)?;
             filter.add(&ffmpeg::filter::find("buffersink").ok_or("ffmpeg format error")?, "out", "")?;
-            filter.output("in", 0)?.input("out", 0)?.parse(&format!("fps=fps={},format=rgba", filter_fps))?;
+            filter.output("in", 0)?.input("out", 0)?.parse(&format!("{crop}fps=fps={},format=rgba", filter_fps))?;
             filter.validate()?;
             (stream.index(), decoder, filter)
         };
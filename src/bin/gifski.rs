@@ -12,7 +12,7 @@ use clap::builder::NonEmptyStringValueParser;
 use clap::error::ErrorKind::MissingRequiredArgument;
 use clap::value_parser;
 use yuv::color::MatrixCoefficients;
-use gifski::{Repeat, Settings};
+use gifski::{Crop, FrameFit, Lossless, OutputFormat, Repeat, Resample, Resize, Settings, TimingMode};
 use std::io::stdin;
 use std::io::BufRead;
 use std::io::BufReader;
@@ -21,15 +21,17 @@ use std::io::Read;
 use std::io::StdinLock;
 use std::io::Stdout;
 
+mod apng_source;
 #[cfg(feature = "video")]
 mod ffmpeg_source;
 mod gif_source;
 mod png;
 mod source;
+mod webp_source;
 mod y4m_source;
 use crate::source::Source;
 
-use gifski::progress::{NoProgress, ProgressReporter};
+use gifski::progress::{NoProgress, ProgressReporter, TimingReport};
 
 pub type BinResult<T, E = Box<dyn std::error::Error + Send + Sync>> = Result<T, E>;
 
@@ -44,9 +46,9 @@ use std::thread;
 use std::time::Duration;
 
 #[cfg(feature = "video")]
-const VIDEO_FRAMES_ARG_HELP: &str = "one video file supported by FFmpeg, or multiple PNG image files";
+const VIDEO_FRAMES_ARG_HELP: &str = "one video file supported by FFmpeg, an animated GIF, APNG or WebP, or multiple PNG image files";
 #[cfg(not(feature = "video"))]
-const VIDEO_FRAMES_ARG_HELP: &str = "PNG image files for the animation frames, or a .y4m file";
+const VIDEO_FRAMES_ARG_HELP: &str = "PNG image files for the animation frames, or a .y4m file, or an animated GIF, APNG or WebP";
 
 fn main() {
     if let Err(e) = bin_main() {
@@ -68,7 +70,7 @@ fn bin_main() -> BinResult<()> {
                         .arg(Arg::new("output")
                             .long("output")
                             .short('o')
-                            .help("Destination file to write to; \"-\" means stdout")
+                            .help("Destination file to write to; \"-\" means stdout. Use .png extension for APNG, .webp for WebP")
                             .num_args(1)
                             .value_name("a.gif")
                             .value_parser(value_parser!(PathBuf))
@@ -81,8 +83,8 @@ fn bin_main() -> BinResult<()> {
                                    kept.\nIf video is used, it will be resampled to \
                                    this constant rate by dropping and/or duplicating \
                                    frames.\nDefault is 20 for videos. No effect for \
-                                   PNG input. For GIF input, it will be used to drop \
-                                   frames if present.")
+                                   PNG input. For GIF, APNG and WebP input, it will be \
+                                   used to drop frames if present.")
                             .value_parser(value_parser!(f32))
                             .value_name("num"))
                         .arg(Arg::new("fast-forward")
@@ -116,12 +118,36 @@ fn bin_main() -> BinResult<()> {
                             .value_parser(value_parser!(u8).range(1..=100))
                             .num_args(1)
                             .help("Lower values reduce motion"))
+                        .arg(Arg::new("dither-stability")
+                            .long("dither-stability")
+                            .value_name("0-100")
+                            .value_parser(value_parser!(u8).range(0..=100))
+                            .num_args(1)
+                            .help("Use the same dither pattern in every frame, and keep pixels that changed only a little"))
+                        .arg(Arg::new("idle-limit")
+                            .long("idle-limit")
+                            .value_name("seconds")
+                            .value_parser(value_parser!(f64))
+                            .num_args(1)
+                            .help("Cut pauses in which nothing moves (except e.g. a blinking cursor) down to this length"))
+                        .arg(Arg::new("merge-threshold")
+                            .long("merge-threshold")
+                            .value_name("0-255")
+                            .value_parser(value_parser!(u8))
+                            .num_args(1)
+                            .help("Merge consecutive frames while their differences add up to at most this much, e.g. 4 for noisy videos"))
                         .arg(Arg::new("lossy-quality")
                             .long("lossy-quality")
                             .value_name("1-100")
                             .value_parser(value_parser!(u8).range(1..=100))
                             .num_args(1)
                             .help("Lower values introduce noise and streaks"))
+                        .arg(Arg::new("max-size")
+                            .long("max-size")
+                            .value_name("8MB")
+                            .value_parser(parse_size)
+                            .num_args(1)
+                            .help("Lower quality and dimensions as needed to fit the file in this size"))
                         .arg(Arg::new("width")
                             .long("width")
                             .short('W')
@@ -135,7 +161,63 @@ fn bin_main() -> BinResult<()> {
                             .num_args(1)
                             .value_parser(value_parser!(u32))
                             .value_name("px")
-                            .help("Maximum height (stretches if the width is also set)"))
+                            .help("Maximum height. Aspect ratio is preserved, unless width is set too"))
+                        .arg(Arg::new("crop")
+                            .long("crop")
+                            .num_args(1)
+                            .value_parser(parse_crop)
+                            .value_name("x,y,w,h")
+                            .help("Keep only this area of the input frames. Done before resizing"))
+                        .arg(Arg::new("auto-crop")
+                            .long("auto-crop")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with("crop")
+                            .help("Remove solid-color borders, such as letterboxing"))
+                        .arg(Arg::new("frame-fit")
+                            .long("frame-fit")
+                            .num_args(1)
+                            .value_parser(parse_frame_fit)
+                            .value_name("center|scale|top-left")
+                            .help("How to fit frames that have a different size than the first one [default: center]"))
+                        .arg(Arg::new("fill")
+                            .long("fill")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .requires("width")
+                            .requires("height")
+                            .help("Cover the whole width x height, cropping the edges that don't fit"))
+                        .arg(Arg::new("no-resize")
+                            .long("no-resize")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with_all(["width", "height"])
+                            .help("Keep the original size, even if it's large"))
+                        .arg(Arg::new("resample")
+                            .long("resample")
+                            .help("Filter for resizing\n\
+                                   point: nearest neighbor, for pixel art\n\
+                                   triangle: soft, without ringing\n\
+                                   catmullrom: sharp, for text and UI graphics\n\
+                                   mitchell: a bit softer than catmullrom\n\
+                                   lanczos3: the sharpest (default)")
+                            .num_args(1)
+                            .hide_short_help(true)
+                            .value_parser(parse_resample)
+                            .value_name("filter"))
+                        .arg(Arg::new("linear-light")
+                            .long("linear-light")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .hide_short_help(true)
+                            .help("Resize in linear light. Slower, but doesn't darken bright details"))
+                        .arg(Arg::new("unsharp")
+                            .long("unsharp")
+                            .num_args(1)
+                            .hide_short_help(true)
+                            .value_parser(value_parser!(f32))
+                            .value_name("0-4")
+                            .help("Sharpen frames after resizing. 0.5 is subtle"))
                         .arg(Arg::new("nosort")
                             .alias("nosort")
                             .long("no-sort")
@@ -175,12 +257,67 @@ fn bin_main() -> BinResult<()> {
                             .action(ArgAction::Append)
                             .value_parser(parse_colors)
                             .value_name("RGBHEX"))
+                        .arg(Arg::new("interlaced")
+                            .long("interlaced")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .hide_short_help(true)
+                            .help("Interlace GIF frames, so that they appear progressively when loading slowly"))
+                        .arg(Arg::new("split-frames")
+                            .long("split-frames")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .hide_short_help(true)
+                            .help("Write far-apart changes as separate images without delay. Smaller, but may play slower in browsers"))
+                        .arg(Arg::new("high-color")
+                            .long("high-color")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .hide_short_help(true)
+                            .help("More than 256 colors in the first frame and after scene cuts. Reduces banding, but makes the file larger"))
+                        .arg(Arg::new("global-palette")
+                            .long("global-palette")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .hide_short_help(true)
+                            .help("Use one palette for all frames. Prevents color flicker in screen recordings"))
+                        .arg(Arg::new("optimize")
+                            .long("optimize")
+                            .num_args(0)
+                            .action(ArgAction::SetTrue)
+                            .hide_short_help(true)
+                            // the GIF is only recompressed, so these would be ignored
+                            .conflicts_with_all(["fps", "fast-forward", "fast", "extra", "quality", "motion-quality", "dither-stability", "idle-limit",
+                                "merge-threshold", "max-size", "width", "height", "crop", "auto-crop", "frame-fit", "fill", "no-resize", "resample",
+                                "linear-light", "unsharp", "repeat", "bounce", "fixed-color", "interlaced", "split-frames", "high-color", "global-palette",
+                                "lossless", "matte", "timing", "y4m-color-override"])
+                            .help("Make an existing GIF smaller, keeping its colors exactly (unless --lossy-quality is set)"))
+                        .arg(Arg::new("lossless")
+                            .long("lossless")
+                            .help("Keep exact colors of pixel art and UI screenshots that have at most 256 colors\n\
+                                   auto: frames with more colors are compressed as usual (default)\n\
+                                   required: fail if any frame has too many colors")
+                            .num_args(0..=1)
+                            .require_equals(true)
+                            .default_missing_value("auto")
+                            .value_parser(parse_lossless)
+                            .value_name("mode"))
                         .arg(Arg::new("matte")
                             .long("matte")
                             .help("Background color for semitransparent pixels")
                             .num_args(1)
                             .value_parser(parse_color)
                             .value_name("RGBHEX"))
+                        .arg(Arg::new("timing")
+                            .long("timing")
+                            .help("How to fit frame timestamps into GIF's 1/100s delays\n\
+                                   compensate: round, and make up for it in the next frames (default)\n\
+                                   strict: fail if timing can't be kept exactly\n\
+                                   50fps: drop frames to get at most 50fps")
+                            .num_args(1)
+                            .hide_short_help(true)
+                            .value_parser(parse_timing)
+                            .value_name("mode"))
                         .arg(Arg::new("y4m-color-override")
                             .long("y4m-color-override")
                             .help("The color space of the input YUV4MPEG2 video\n\
@@ -215,22 +352,43 @@ fn bin_main() -> BinResult<()> {
         _ => Repeat::Finite(repeat_int as u16),
     };
 
-    let extra = matches.get_flag("extra");
-    let motion_quality = matches.get_one::<u8>("motion-quality").copied();
-    let lossy_quality = matches.get_one::<u8>("lossy-quality").copied();
-    let fast = matches.get_flag("fast");
-    let settings = Settings {
-        width,
-        height,
-        quality: matches.get_one::<u8>("quality").copied().unwrap_or(100),
-        fast,
-        repeat,
+    let mut settings = Settings::default();
+    settings.crop = if matches.get_flag("auto-crop") {
+        Crop::Auto
+    } else {
+        matches.get_one::<Crop>("crop").copied().unwrap_or_default()
     };
+    settings.resize = match (width, height) {
+        (Some(width), Some(height)) if matches.get_flag("fill") => Resize::Fill { width, height },
+        (None, None) if matches.get_flag("no-resize") => Resize::None,
+        (width, height) => Resize::from_width_height(width, height),
+    };
+    settings.frame_fit = matches.get_one::<FrameFit>("frame-fit").copied().unwrap_or_default();
+    settings.resample = matches.get_one::<Resample>("resample").copied().unwrap_or_default();
+    settings.linear_light = matches.get_flag("linear-light");
+    settings.unsharp = matches.get_one::<f32>("unsharp").copied().unwrap_or(0.);
+    settings.quality = matches.get_one::<u8>("quality").copied().unwrap_or(100);
+    settings.fast = matches.get_flag("fast");
+    settings.repeat = repeat;
+    settings.max_size = matches.get_one::<u64>("max-size").copied();
+    settings.global_palette = matches.get_flag("global-palette");
+    settings.high_color = matches.get_flag("high-color");
+    settings.interlaced = matches.get_flag("interlaced");
+    settings.split_frames = matches.get_flag("split-frames");
+    settings.motion_quality = matches.get_one::<u8>("motion-quality").copied();
+    settings.lossy_quality = matches.get_one::<u8>("lossy-quality").copied();
+    settings.extra_effort = matches.get_flag("extra");
+    settings.dither_stability = matches.get_one::<u8>("dither-stability").copied().unwrap_or(0);
+    settings.idle_limit = matches.get_one::<f64>("idle-limit").copied();
+    settings.merge_threshold = matches.get_one::<u8>("merge-threshold").copied().unwrap_or(0);
+    settings.matte = matches.get_one::<rgb::RGB8>("matte").copied();
+    settings.format = output_path.format();
+    settings.timing = matches.get_one::<TimingMode>("timing").copied().unwrap_or_default();
+    settings.lossless = matches.get_one::<Lossless>("lossless").copied().unwrap_or_default();
     let quiet = matches.get_flag("quiet") || output_path == DestPath::Stdout;
     let fps: Option<f32> = matches.get_one::<f32>("fps").copied();
     let speed: f32 = matches.get_one::<f32>("fast-forward").copied().ok_or("?")?;
     let fixed_colors = matches.get_many::<Vec<rgb::RGB8>>("fixed-color");
-    let matte = matches.get_one::<rgb::RGB8>("matte");
     let in_color_space = matches.get_one::<MatrixCoefficients>("y4m-color-override").copied();
 
     let rate = source::Fps { fps, speed };
@@ -259,6 +417,10 @@ fn bin_main() -> BinResult<()> {
 
     check_if_paths_exist(&frames)?;
 
+    if matches.get_flag("optimize") {
+        return optimize(&frames, &output_path, settings.lossy_quality, quiet);
+    }
+
     std::thread::scope(move |scope| {
 
     let (mut collector, mut writer) = gifski::new(settings)?;
@@ -267,27 +429,11 @@ fn bin_main() -> BinResult<()> {
             writer.add_fixed_color(*f);
         }
     }
-    if let Some(matte) = matte {
-        #[allow(deprecated)]
-        writer.set_matte_color(*matte);
-    }
-    if extra {
-        #[allow(deprecated)]
-        writer.set_extra_effort(true);
-    }
-    if let Some(motion_quality) = motion_quality {
-        #[allow(deprecated)]
-        writer.set_motion_quality(motion_quality);
-    }
-    if let Some(lossy_quality) = lossy_quality {
-        #[allow(deprecated)]
-        writer.set_lossy_quality(lossy_quality);
-    }
 
     let (decoder_ready_send, decoder_ready_recv) = crossbeam_channel::bounded(1);
 
     let decode_thread = thread::Builder::new().name("decode".into()).spawn_scoped(scope, move || {
-        let mut decoder = if let [path] = &frames[..] {
+        let mut decoder: Box<dyn Source> = if let [path] = &frames[..] {
             if bounce {
                 eprintln!("warning: the bounce flag is supported only for individual files, not pipe or video");
             }
@@ -301,10 +447,14 @@ fn bin_main() -> BinResult<()> {
                 SrcPath::Path(path.clone())
             };
             match file_type(&mut src).unwrap_or(FileType::Other) {
+                FileType::PNG if apng_source::is_animated(&mut src)? => {
+                    Box::new(apng_source::ApngDecoder::new(src, rate)?)
+                },
+                FileType::WebP => Box::new(webp_source::WebPDecoder::new(src, rate)?),
                 FileType::PNG | FileType::JPEG => return Err("Only a single image file was given as an input. This is not enough to make an animation.".into()),
                 FileType::GIF => {
                     if !quiet && (width.is_none() && settings.quality > 50) {
-                        eprintln!("warning: reading an existing GIF as an input. This can only worsen the quality. Use PNG frames instead.");
+                        eprintln!("warning: reading an existing GIF as an input. This can only worsen the quality. Use PNG frames instead, or --optimize to keep the colors.");
                     }
                     Box::new(gif_source::GifDecoder::new(src, rate)?)
                 },
@@ -331,6 +481,7 @@ fn bin_main() -> BinResult<()> {
                 },
                 FileType::GIF => return unexpected("GIF"),
                 FileType::Y4M => return unexpected("Y4M"),
+                FileType::WebP => return unexpected("WebP"),
                 _ => Box::new(png::Lodecoder::new(frames, rate)),
             }
         };
@@ -405,6 +556,33 @@ fn bin_main() -> BinResult<()> {
     })
 }
 
+/// Re-encodes an existing GIF without quantizing it again
+fn optimize(frames: &[PathBuf], output_path: &DestPath, lossy_quality: Option<u8>, quiet: bool) -> BinResult<()> {
+    let [path] = frames else {
+        return Err("--optimize takes one GIF file as the input".into());
+    };
+    if output_path.format() != OutputFormat::Gif {
+        return Err("--optimize can only write GIF files".into());
+    }
+    let input: Box<dyn Read> = if path.as_os_str() == "-" {
+        Box::new(BufReader::new(stdin().lock()))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let output: Box<dyn io::Write> = match output_path {
+        DestPath::Path(out) if out.canonicalize().ok().is_some_and(|out| path.canonicalize().ok() == Some(out)) => {
+            return Err("--optimize can't overwrite its input file".into());
+        },
+        DestPath::Path(out) => Box::new(File::create(out).map_err(|err| format!("Can't write to \"{}\": {err}", out.display()))?),
+        DestPath::Stdout => Box::new(io::stdout().lock()),
+    };
+    gifski::optimize_gif(input, io::BufWriter::new(output), lossy_quality)?;
+    if !quiet {
+        eprintln!("gifski optimized {output_path}");
+    }
+    Ok(())
+}
+
 fn check_errors(err1: Result<(), gifski::Error>, err2: BinResult<()>) -> BinResult<()> {
     use gifski::Error::*;
     match err1 {
@@ -454,6 +632,78 @@ fn color_parser() {
     assert!(parse_colors("#12345").is_err());
 }
 
+/// Decimal units, because that's what upload limits usually use
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (num, unit) = size.split_at(size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len()));
+    let num = num.parse::<f64>().map_err(|_| format!("size must be a number of bytes, or have a KB or MB suffix, not '{size}'"))?;
+    let unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "kib" => 1 << 10,
+        "m" | "mb" => 1_000_000,
+        "mib" => 1 << 20,
+        _ => return Err(format!("unrecognized size unit '{unit}'")),
+    };
+    Ok((num * f64::from(unit)) as u64)
+}
+
+#[test]
+fn size_parser() {
+    assert_eq!(parse_size("8MB").unwrap(), 8_000_000);
+    assert_eq!(parse_size("1.5 mib").unwrap(), 3 << 19);
+    assert_eq!(parse_size("500kb").unwrap(), 500_000);
+    assert_eq!(parse_size("1234").unwrap(), 1234);
+    assert!(parse_size("MB").is_err());
+    assert!(parse_size("5GB").is_err());
+}
+
+fn parse_timing(value: &str) -> Result<TimingMode, String> {
+    Ok(match value.trim().to_lowercase().as_str() {
+        "compensate" => TimingMode::Compensate,
+        "strict" => TimingMode::Strict,
+        "50fps" => TimingMode::Max50Fps,
+        _ => return Err("timing must be compensate, strict or 50fps".into()),
+    })
+}
+
+fn parse_crop(value: &str) -> Result<Crop, String> {
+    let parts = value.split(',').map(|n| n.trim().parse::<u32>()).collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("crop must be x,y,width,height: {e}"))?;
+    match parts[..] {
+        [left, top, width, height] if width > 0 && height > 0 => Ok(Crop::Rect { left, top, width, height }),
+        _ => Err("crop must be x,y,width,height, e.g. 0,60,1280,600".into()),
+    }
+}
+
+fn parse_frame_fit(value: &str) -> Result<FrameFit, String> {
+    Ok(match value.trim().to_lowercase().as_str() {
+        "center" | "pad" => FrameFit::Center,
+        "scale" => FrameFit::Scale,
+        "top-left" | "topleft" => FrameFit::TopLeft,
+        _ => return Err("frame-fit must be center, scale or top-left".into()),
+    })
+}
+
+fn parse_lossless(value: &str) -> Result<Lossless, String> {
+    Ok(match value.trim().to_lowercase().as_str() {
+        "auto" => Lossless::IfPossible,
+        "required" | "strict" => Lossless::Required,
+        _ => return Err("lossless must be auto or required".into()),
+    })
+}
+
+fn parse_resample(value: &str) -> Result<Resample, String> {
+    Ok(match value.trim().to_lowercase().as_str() {
+        "point" | "nearest" => Resample::Point,
+        "triangle" | "bilinear" => Resample::Triangle,
+        "catmullrom" | "catrom" => Resample::CatmullRom,
+        "mitchell" => Resample::Mitchell,
+        "lanczos3" | "lanczos" => Resample::Lanczos3,
+        _ => return Err("resample filter must be point, triangle, catmullrom, mitchell or lanczos3".into()),
+    })
+}
+
 fn parse_color_space(value: &str) -> Result<MatrixCoefficients, String> {
     let value = value.to_lowercase();
     let value = value.trim();
@@ -472,37 +722,45 @@ fn parse_color_space(value: &str) -> Result<MatrixCoefficients, String> {
 #[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq)]
 enum FileType {
-    PNG, GIF, JPEG, Y4M, Other,
+    PNG, GIF, JPEG, Y4M, WebP, Other,
 }
 
 fn file_type(src: &mut SrcPath) -> BinResult<FileType> {
-    let mut buf = [0; 4];
+    let mut buf = [0; 12];
     match src {
         SrcPath::Path(path) => match path.extension() {
             Some(e) if e.eq_ignore_ascii_case("y4m") => return Ok(FileType::Y4M),
             Some(e) if e.eq_ignore_ascii_case("png") => return Ok(FileType::PNG),
+            Some(e) if e.eq_ignore_ascii_case("webp") => return Ok(FileType::WebP),
             _ => {
                 let mut file = std::fs::File::open(path)?;
-                file.read_exact(&mut buf)?;
+                file.read_exact(&mut buf[..4])?;
+                // RIFF is a container, and the format is after its size
+                if &buf[..4] == b"RIFF" {
+                    file.read_exact(&mut buf[4..])?;
+                }
             },
         },
         SrcPath::Stdin(stdin) => {
             let buf_in = stdin.fill_buf()?;
-            let max_len = buf_in.len().min(4);
+            let max_len = buf_in.len().min(buf.len());
             buf[..max_len].copy_from_slice(&buf_in[..max_len]);
             // don't consume
         },
     }
 
-    if &buf == b"\x89PNG" {
+    if &buf[..4] == b"\x89PNG" {
         return Ok(FileType::PNG);
     }
-    if &buf == b"GIF8" {
+    if &buf[..4] == b"GIF8" {
         return Ok(FileType::GIF);
     }
-    if &buf == b"YUV4" {
+    if &buf[..4] == b"YUV4" {
         return Ok(FileType::Y4M);
     }
+    if &buf[..4] == b"RIFF" && &buf[8..] == b"WEBP" {
+        return Ok(FileType::WebP);
+    }
     if buf[..2] == [0xFF, 0xD8] {
         return Ok(FileType::JPEG);
     }
@@ -563,6 +821,18 @@ impl<'a> DestPath<'a> {
             Self::Path(Path::new(path))
         }
     }
+
+    /// Chosen by file extension. Stdout is always GIF.
+    pub fn format(&self) -> OutputFormat {
+        let Self::Path(path) = self else {
+            return OutputFormat::Gif;
+        };
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase()).as_deref() {
+            Some("png" | "apng") => OutputFormat::Apng,
+            Some("webp") => OutputFormat::WebP,
+            _ => OutputFormat::Gif,
+        }
+    }
 }
 
 impl fmt::Display for DestPath<'_> {
@@ -621,6 +891,7 @@ struct ProgressBar {
     total: Option<u64>,
     previous_estimate: u64,
     displayed_estimate: u64,
+    timing: Option<TimingReport>,
 }
 impl ProgressBar {
     fn new(total: Option<u64>) -> Self {
@@ -631,7 +902,7 @@ impl ProgressBar {
         pb.message("Frame ");
         pb.set_max_refresh_rate(Some(Duration::from_millis(250)));
         Self {
-            pb, frames: 0, total, previous_estimate: 0, displayed_estimate: 0,
+            pb, frames: 0, total, previous_estimate: 0, displayed_estimate: 0, timing: None,
         }
     }
 }
@@ -664,7 +935,14 @@ impl ProgressReporter for ProgressBar {
         }
     }
 
+    fn timing(&mut self, report: &TimingReport) {
+        self.timing = Some(report.clone());
+    }
+
     fn done(&mut self, msg: &str) {
         self.pb.finish_print(msg);
+        if let Some(t) = self.timing.as_ref().filter(|t| t.final_drift >= 0.1) {
+            eprintln!("warning: the animation is {:.1}s longer than the input, because GIF can't show frames for less than 0.02s. Use --timing=50fps to drop frames instead", t.final_drift);
+        }
     }
 }
@@ -0,0 +1,77 @@
+//! This is for reading animated WebP as an input
+
+use crate::source::{Fps, Source, DEFAULT_FPS};
+use crate::{BinResult, SrcPath};
+use gifski::Collector;
+use imgref::ImgVec;
+use rgb::RGBA8;
+use std::io::{Cursor, Read};
+
+pub struct WebPDecoder {
+    fps: Option<f32>,
+    speed: f32,
+    decoder: image_webp::WebPDecoder<Cursor<Vec<u8>>>,
+}
+
+impl WebPDecoder {
+    pub fn new(src: SrcPath, fps: Fps) -> BinResult<Self> {
+        // the decoder needs to seek
+        let data = match src {
+            SrcPath::Path(path) => std::fs::read(path)?,
+            SrcPath::Stdin(mut buf) => {
+                let mut data = Vec::new();
+                buf.read_to_end(&mut data)?;
+                data
+            },
+        };
+
+        let decoder = image_webp::WebPDecoder::new(Cursor::new(data))?;
+        if !decoder.is_animated() {
+            return Err("Only a single image file was given as an input. This is not enough to make an animation.".into());
+        }
+
+        Ok(Self {
+            fps: fps.fps,
+            speed: fps.speed,
+            decoder,
+        })
+    }
+}
+
+impl Source for WebPDecoder {
+    fn total_frames(&self) -> Option<u64> {
+        if self.fps.is_some() { None } else { Some(self.decoder.num_frames().into()) }
+    }
+
+    fn collect(&mut self, c: &mut Collector) -> BinResult<()> {
+        let (width, height) = self.decoder.dimensions();
+        let (width, height) = (width as usize, height as usize);
+        let has_alpha = self.decoder.has_alpha();
+        let mut buf = vec![0; self.decoder.output_buffer_size().ok_or("WebP is too large")?];
+
+        let mut idx = 0;
+        let mut delay_ts = 0;
+        let skip_frames = self.fps.is_some();
+        let wanted_frame_time = 1. / f64::from(self.fps.unwrap_or(DEFAULT_FPS));
+        let mut wanted_presentation_timestamp = 0.;
+        for _ in 0..self.decoder.num_frames() {
+            // frames are already composited on the canvas, and the delay is in milliseconds
+            let delay = self.decoder.read_frame(&mut buf)?;
+            let presentation_timestamp = delay_ts as f64 / (1000. * f64::from(self.speed));
+            delay_ts += u64::from(delay);
+            if skip_frames && presentation_timestamp < wanted_presentation_timestamp {
+                continue;
+            }
+            wanted_presentation_timestamp += wanted_frame_time;
+
+            let pixels = if has_alpha {
+                buf.chunks_exact(4).map(|c| RGBA8::new(c[0], c[1], c[2], c[3])).collect()
+            } else {
+                buf.chunks_exact(3).map(|c| RGBA8::new(c[0], c[1], c[2], 255)).collect()
+            };
+            c.add_frame_rgba(idx, ImgVec::new(pixels, width, height), presentation_timestamp)?;
+            idx += 1;
+        }
+        Ok(())
+    }
+}
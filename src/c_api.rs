@@ -43,8 +43,9 @@
 //!
 //! it will build `target/aarch64-apple-ios/release/libgifski.a` (ignore the warning about cdylib).
 
+use crate::collector::FrameTime;
 use crate::progress::ProgressCallback;
-use crate::{CCallbacks, Collector, ErrorCallback, ProgressReporter, Repeat, Settings, Writer};
+use crate::{CCallbacks, Collector, ErrorCallback, ProgressReporter, Repeat, Resize, Settings, Writer};
 use imgref::{Img, ImgVec};
 use rgb::{RGB8, RGBA8};
 use std::fs;
@@ -67,9 +68,9 @@ use std::panic::catch_unwind;
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct GifskiSettings {
-    /// Resize to max this width if non-0.
+    /// Resize to max this width if non-0. See also `gifski_set_resize`.
     pub width: u32,
-    /// Resize to max this height if width is non-0. Note that aspect ratio is not preserved.
+    /// Resize to max this height if non-0. Aspect ratio is preserved, unless `width` is non-0 too.
     pub height: u32,
     /// 1-100, but useful range is 50-100. Recommended to set to 90.
     pub quality: u8,
@@ -115,11 +116,11 @@ pub unsafe extern "C" fn gifski_new(settings: *const GifskiSettings) -> *const G
         return ptr::null_mut();
     };
     let s = Settings {
-        width: if settings.width > 0 { Some(settings.width) } else { None },
-        height: if settings.height > 0 { Some(settings.height) } else { None },
+        resize: Resize::from_width_height((settings.width > 0).then_some(settings.width), (settings.height > 0).then_some(settings.height)),
         quality: settings.quality,
         fast: settings.fast,
         repeat: if settings.repeat == -1 { Repeat::Finite(0) } else if settings.repeat == 0 { Repeat::Infinite } else { Repeat::Finite(settings.repeat as u16) },
+        ..Settings::default()
     };
 
     if let Ok((collector, writer)) = crate::new(s) {
@@ -140,14 +141,62 @@ pub unsafe extern "C" fn gifski_new(settings: *const GifskiSettings) -> *const G
 
 /// Quality 1-100 of temporal denoising. Lower values reduce motion. Defaults to `settings.quality`.
 ///
+/// Returns `GIFSKI_INVALID_INPUT` if the quality is out of range. Versions before 2.0 accepted any value.
 /// Only valid immediately after calling `gifski_new`, before any frames are added.
 #[no_mangle]
 pub unsafe extern "C" fn gifski_set_motion_quality(handle: *mut GifskiHandle, quality: u8) -> GifskiError {
     let Some(g) = borrow(handle) else { return GifskiError::NULL_ARG };
 
     if let Ok(Some(w)) = g.writer.lock().as_deref_mut() {
-        #[allow(deprecated)]
-        w.set_motion_quality(quality);
+        if quality == 0 || quality > 100 {
+            return GifskiError::INVALID_INPUT;
+        }
+        w.settings.motion_quality = Some(quality);
+        GifskiError::OK
+    } else {
+        GifskiError::INVALID_STATE
+    }
+}
+
+// The same as `enum GifskiResizeMode` in `gifski.h`
+pub const GIFSKI_RESIZE_AUTO: c_int = 0;
+pub const GIFSKI_RESIZE_NONE: c_int = 1;
+pub const GIFSKI_RESIZE_FIT: c_int = 2;
+pub const GIFSKI_RESIZE_FILL: c_int = 3;
+pub const GIFSKI_RESIZE_EXACT: c_int = 4;
+pub const GIFSKI_RESIZE_SCALE: c_int = 5;
+pub const GIFSKI_RESIZE_MAX_PIXELS: c_int = 6;
+
+/// Overrides `width`/`height` of `GifskiSettings`. `mode` is one of `GIFSKI_RESIZE_*` constants:
+///
+/// * `GIFSKI_RESIZE_AUTO` (0) downscales to about 800×600,
+/// * `GIFSKI_RESIZE_NONE` (1) keeps the original size,
+/// * `GIFSKI_RESIZE_FIT` (2) fits within `width`×`height` (0 means no limit), without upscaling,
+/// * `GIFSKI_RESIZE_FILL` (3) covers `width`×`height` and crops the rest,
+/// * `GIFSKI_RESIZE_EXACT` (4) stretches to `width`×`height`,
+/// * `GIFSKI_RESIZE_SCALE` (5) multiplies both dimensions by `scale`,
+/// * `GIFSKI_RESIZE_MAX_PIXELS` (6) downscales to have at most `width`×`height` pixels, keeping the aspect ratio.
+///
+/// Only valid immediately after calling `gifski_new`, before any frames are added.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_resize(handle: *mut GifskiHandle, mode: c_int, width: u32, height: u32, scale: f32) -> GifskiError {
+    let Some(g) = borrow(handle) else { return GifskiError::NULL_ARG };
+
+    if let Ok(Some(w)) = g.writer.lock().as_deref_mut() {
+        let resize = match mode {
+            GIFSKI_RESIZE_AUTO => Resize::Auto,
+            GIFSKI_RESIZE_NONE => Resize::None,
+            GIFSKI_RESIZE_FIT => Resize::Fit { width: (width > 0).then_some(width), height: (height > 0).then_some(height) },
+            GIFSKI_RESIZE_FILL => Resize::Fill { width, height },
+            GIFSKI_RESIZE_EXACT => Resize::Exact { width, height },
+            GIFSKI_RESIZE_SCALE => Resize::Scale(scale),
+            GIFSKI_RESIZE_MAX_PIXELS => Resize::MaxPixels(width.saturating_mul(height)),
+            _ => return GifskiError::INVALID_INPUT,
+        };
+        if resize.validate().is_err() {
+            return GifskiError::INVALID_INPUT;
+        }
+        w.settings.resize = resize;
         GifskiError::OK
     } else {
         GifskiError::INVALID_STATE
@@ -157,14 +206,17 @@ pub unsafe extern "C" fn gifski_set_motion_quality(handle: *mut GifskiHandle, qu
 /// Quality 1-100 of gifsicle compression. Lower values add noise. Defaults to `settings.quality`.
 ///
 /// Has no effect if the `gifsicle` feature hasn't been enabled.
+/// Returns `GIFSKI_INVALID_INPUT` if the quality is out of range. Versions before 2.0 accepted any value.
 /// Only valid immediately after calling `gifski_new`, before any frames are added.
 #[no_mangle]
 pub unsafe extern "C" fn gifski_set_lossy_quality(handle: *mut GifskiHandle, quality: u8) -> GifskiError {
     let Some(g) = borrow(handle) else { return GifskiError::NULL_ARG };
 
     if let Ok(Some(w)) = g.writer.lock().as_deref_mut() {
-        #[allow(deprecated)]
-        w.set_lossy_quality(quality);
+        if quality == 0 || quality > 100 {
+            return GifskiError::INVALID_INPUT;
+        }
+        w.settings.lossy_quality = Some(quality);
         GifskiError::OK
     } else {
         GifskiError::INVALID_STATE
@@ -179,8 +231,22 @@ pub unsafe extern "C" fn gifski_set_extra_effort(handle: *mut GifskiHandle, extr
     let Some(g) = borrow(handle) else { return GifskiError::NULL_ARG };
 
     if let Ok(Some(w)) = g.writer.lock().as_deref_mut() {
-        #[allow(deprecated)]
-        w.set_extra_effort(extra);
+        w.settings.extra_effort = extra;
+        GifskiError::OK
+    } else {
+        GifskiError::INVALID_STATE
+    }
+}
+
+/// Semitransparent pixels will be blended with this background color, instead of having their transparency dithered.
+///
+/// Only valid immediately after calling `gifski_new`, before any frames are added.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_set_matte_color(handle: *mut GifskiHandle, col_r: u8, col_g: u8, col_b: u8) -> GifskiError {
+    let Some(g) = borrow(handle) else { return GifskiError::NULL_ARG };
+
+    if let Ok(Some(w)) = g.writer.lock().as_deref_mut() {
+        w.settings.matte = Some(RGB8::new(col_r, col_g, col_b));
         GifskiError::OK
     } else {
         GifskiError::INVALID_STATE
@@ -223,6 +289,27 @@ pub unsafe extern "C" fn gifski_add_fixed_color(handle: *mut GifskiHandle, col_r
 #[no_mangle]
 #[cfg(feature = "png")]
 pub unsafe extern "C" fn gifski_add_frame_png_file(handle: *const GifskiHandle, frame_number: u32, file_path: *const c_char, presentation_timestamp: f64) -> GifskiError {
+    add_frame_png_file(handle, frame_number, file_path, FrameTime::Pts(presentation_timestamp))
+}
+
+/// Same as `gifski_add_frame_png_file`, but instead of a timestamp, it takes time in seconds for how long this frame is to be displayed.
+/// The frame starts when the previous frame ends.
+///
+/// The duration is rounded to whole centiseconds, and used exactly, including for the last frame.
+/// Durations shorter than 0.01s are made 0.01s, and GIF frames shorter than 0.02s are lengthened.
+///
+/// Use either durations or timestamps for all frames, not both.
+#[no_mangle]
+#[cfg(feature = "png")]
+pub unsafe extern "C" fn gifski_add_frame_png_file_with_duration(handle: *const GifskiHandle, frame_number: u32, file_path: *const c_char, duration: f64) -> GifskiError {
+    if duration.is_nan() || duration <= 0. {
+        return GifskiError::INVALID_INPUT;
+    }
+    add_frame_png_file(handle, frame_number, file_path, FrameTime::Duration(duration))
+}
+
+#[cfg(feature = "png")]
+unsafe fn add_frame_png_file(handle: *const GifskiHandle, frame_number: u32, file_path: *const c_char, time: FrameTime) -> GifskiError {
     if file_path.is_null() {
         return GifskiError::NULL_ARG;
     }
@@ -234,7 +321,10 @@ pub unsafe extern "C" fn gifski_add_frame_png_file(handle: *const GifskiHandle,
         return GifskiError::INVALID_INPUT;
     };
     if let Ok(Some(c)) = g.collector.lock().as_deref_mut() {
-        c.add_frame_png_file(frame_number as usize, path, presentation_timestamp).into()
+        match time {
+            FrameTime::Pts(pts) => c.add_frame_png_file(frame_number as usize, path, pts),
+            FrameTime::Duration(duration) => c.add_frame_png_file_with_duration(frame_number as usize, path, duration),
+        }.into()
     } else {
         g.print_error(format!("frame {frame_number} can't be added any more, because gifski_end_adding_frames has been called already"));
         GifskiError::INVALID_STATE
@@ -276,7 +366,7 @@ pub unsafe extern "C" fn gifski_add_frame_rgba(handle: *const GifskiHandle, fram
     let width = width as usize;
     let height = height as usize;
     let pixels = slice::from_raw_parts(pixels, width * height);
-    add_frame_rgba(handle, frame_number, Img::new(pixels.into(), width, height), presentation_timestamp)
+    add_frame_rgba(handle, frame_number, Img::new(pixels.into(), width, height), FrameTime::Pts(presentation_timestamp))
 }
 
 /// Same as `gifski_add_frame_rgba`, but with bytes per row arg.
@@ -287,7 +377,27 @@ pub unsafe extern "C" fn gifski_add_frame_rgba_stride(handle: *const GifskiHandl
         Err(err) => return err,
     };
     let img = ImgVec::new_stride(pixels.into(), width as _, height as _, stride);
-    add_frame_rgba(handle, frame_number, img, presentation_timestamp)
+    add_frame_rgba(handle, frame_number, img, FrameTime::Pts(presentation_timestamp))
+}
+
+/// Same as `gifski_add_frame_rgba_stride`, but instead of a timestamp, it takes time in seconds for how long this frame is to be displayed.
+/// The frame starts when the previous frame ends.
+///
+/// The duration is rounded to whole centiseconds, and used exactly, including for the last frame.
+/// Durations shorter than 0.01s are made 0.01s, and GIF frames shorter than 0.02s are lengthened.
+///
+/// Use either durations or timestamps for all frames, not both.
+#[no_mangle]
+pub unsafe extern "C" fn gifski_add_frame_rgba_stride_with_duration(handle: *const GifskiHandle, frame_number: u32, width: u32, height: u32, bytes_per_row: u32, pixels: *const RGBA8, duration: f64) -> GifskiError {
+    if duration.is_nan() || duration <= 0. {
+        return GifskiError::INVALID_INPUT;
+    }
+    let (pixels, stride) = match pixels_slice(pixels, width, height, bytes_per_row) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+    let img = ImgVec::new_stride(pixels.into(), width as _, height as _, stride);
+    add_frame_rgba(handle, frame_number, img, FrameTime::Duration(duration))
 }
 
 unsafe fn pixels_slice<'a, T>(pixels: *const T, width: u32, height: u32, bytes_per_row: u32) -> Result<(&'a [T], usize), GifskiError> {
@@ -304,11 +414,14 @@ unsafe fn pixels_slice<'a, T>(pixels: *const T, width: u32, height: u32, bytes_p
     Ok((pixels, stride))
 }
 
-fn add_frame_rgba(handle: *const GifskiHandle, frame_number: u32, frame: ImgVec<RGBA8>, presentation_timestamp: f64) -> GifskiError {
+fn add_frame_rgba(handle: *const GifskiHandle, frame_number: u32, frame: ImgVec<RGBA8>, time: FrameTime) -> GifskiError {
     let Some(g) = (unsafe { borrow(handle) }) else { return GifskiError::NULL_ARG };
 
     if let Ok(Some(c)) = g.collector.lock().as_deref_mut() {
-        c.add_frame_rgba(frame_number as usize, frame, presentation_timestamp).into()
+        match time {
+            FrameTime::Pts(pts) => c.add_frame_rgba(frame_number as usize, frame, pts),
+            FrameTime::Duration(duration) => c.add_frame_rgba_with_duration(frame_number as usize, frame, duration),
+        }.into()
     } else {
         g.print_error(format!("frame {frame_number} can't be added any more, because gifski_end_adding_frames has been called already"));
         GifskiError::INVALID_STATE
@@ -340,7 +453,7 @@ pub unsafe extern "C" fn gifski_add_frame_argb(handle: *const GifskiHandle, fram
         b: p.b,
         a: p.a,
     })).collect(), width, height);
-    add_frame_rgba(handle, frame_number, img, presentation_timestamp)
+    add_frame_rgba(handle, frame_number, img, FrameTime::Pts(presentation_timestamp))
 }
 
 /// Same as `gifski_add_frame_rgba`, except it expects RGB components (3 bytes per pixel).
@@ -363,7 +476,7 @@ pub unsafe extern "C" fn gifski_add_frame_rgb(handle: *const GifskiHandle, frame
     let width = width as usize;
     let height = height as usize;
     let img = ImgVec::new(pixels.chunks(stride).flat_map(|r| r[0..width].iter().map(|&p| p.with_alpha(255))).collect(), width, height);
-    add_frame_rgba(handle, frame_number, img, presentation_timestamp)
+    add_frame_rgba(handle, frame_number, img, FrameTime::Pts(presentation_timestamp))
 }
 
 /// Get a callback for frame processed, and abort processing if desired.
@@ -649,6 +762,37 @@ fn c_cb() {
     assert_eq!(2, progress_called);
 }
 
+#[test]
+fn c_frame_durations() {
+    let g = unsafe { gifski_new(&GifskiSettings {
+        width: 0, height: 0,
+        quality: 100,
+        fast: false,
+        repeat: 0,
+    })};
+    assert!(!g.is_null());
+    let mut out = Vec::<u8>::new();
+    unsafe extern "C" fn cb(len: usize, buf: *const u8, user_data: *mut c_void) -> c_int {
+        let out = user_data.cast::<Vec<u8>>();
+        (*out).extend_from_slice(slice::from_raw_parts(buf, len));
+        0
+    }
+    let pixels = [RGBA8::new(0, 0, 0, 255), RGBA8::new(255, 255, 255, 255)];
+    unsafe {
+        assert_eq!(GifskiError::OK, gifski_set_write_callback(g, Some(cb), ptr::addr_of_mut!(out).cast()));
+        assert_eq!(GifskiError::INVALID_INPUT, gifski_add_frame_rgba_stride_with_duration(g, 0, 1, 1, 4, &pixels[0], 0.));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgba_stride_with_duration(g, 0, 1, 1, 4, &pixels[0], 0.3));
+        assert_eq!(GifskiError::OK, gifski_add_frame_rgba_stride_with_duration(g, 1, 1, 1, 4, &pixels[1], 1.25));
+        assert_eq!(GifskiError::OK, gifski_finish(g));
+    }
+    let mut decoder = gif::DecodeOptions::new().read_info(&out[..]).unwrap();
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays, [30, 125]);
+}
+
 #[test]
 fn progress_abort() {
     use rgb::RGB;
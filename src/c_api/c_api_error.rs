@@ -85,7 +85,8 @@ impl From<GifResult<()>> for GifskiError {
                 Aborted => Self::ABORTED,
                 Gifsicle | Gif(_) => Self::GIF,
                 NoFrames => Self::INVALID_STATE,
-                WrongSize(_) => Self::INVALID_INPUT,
+                TooLarge | WebP(_) => Self::OTHER,
+                WrongSize(_) | InvalidSettings(_) | Timing(_) | TooManyColors(_) | GifDecode(_) => Self::INVALID_INPUT,
                 PNG(_) => Self::OTHER,
             },
         }
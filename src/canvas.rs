@@ -0,0 +1,115 @@
+//! Frames that don't have the size of the animation
+
+use crate::error::CatResult;
+use crate::{resized_binary_alpha, FrameFit, Resize, Settings};
+#[cfg(test)]
+use crate::Resample;
+use imgref::{ImgRef, ImgVec};
+use rgb::RGBA8;
+
+/// Draws the frame at its position on a transparent canvas. Parts outside of the canvas are clipped.
+pub(crate) fn place(image: ImgRef<RGBA8>, left: usize, top: usize, canvas_width: usize, canvas_height: usize) -> ImgVec<RGBA8> {
+    let mut canvas = ImgVec::new(vec![RGBA8::new(0, 0, 0, 0); canvas_width * canvas_height], canvas_width, canvas_height);
+    blit(image, (0, 0), &mut canvas, (left, top));
+    canvas
+}
+
+/// Makes the frame look as if it had been drawn on a `canvas_width`×`canvas_height` canvas according to the `FrameFit` policy,
+/// and then resized like frames that have the canvas size.
+pub(crate) fn fit(image: ImgVec<RGBA8>, canvas_width: usize, canvas_height: usize, settings: &Settings) -> CatResult<ImgVec<RGBA8>> {
+    if settings.frame_fit == FrameFit::Scale {
+        return scale_to_fit(image, canvas_width, canvas_height, settings);
+    }
+    // frames larger than the canvas are cropped, smaller ones are padded
+    let offset = |size: usize, canvas_size: usize| {
+        if settings.frame_fit == FrameFit::TopLeft { 0 } else { (canvas_size as isize - size as isize) / 2 }
+    };
+    let mut canvas = ImgVec::new(vec![RGBA8::new(0, 0, 0, 0); canvas_width * canvas_height], canvas_width, canvas_height);
+    blit_at(image.as_ref(), &mut canvas, offset(image.width(), canvas_width), offset(image.height(), canvas_height));
+    resized_binary_alpha(canvas, settings)
+}
+
+/// Resampled only once, straight to the size it would have in the resized canvas
+fn scale_to_fit(image: ImgVec<RGBA8>, canvas_width: usize, canvas_height: usize, settings: &Settings) -> CatResult<ImgVec<RGBA8>> {
+    let ((crop_left, crop_top, crop_width, crop_height), (width, height)) = settings.resize.plan((canvas_width, canvas_height));
+    let factor = (canvas_width as f64 / image.width() as f64).min(canvas_height as f64 / image.height() as f64);
+    // centered on the canvas, in pixels of the input
+    let scaled_width = image.width() as f64 * factor;
+    let scaled_height = image.height() as f64 * factor;
+    let left = (canvas_width as f64 - scaled_width) / 2. - crop_left as f64;
+    let top = (canvas_height as f64 - scaled_height) / 2. - crop_top as f64;
+
+    let (x_factor, y_factor) = (width as f64 / crop_width as f64, height as f64 / crop_height as f64);
+    let resize = Resize::Exact {
+        width: ((scaled_width * x_factor).round() as u32).max(1),
+        height: ((scaled_height * y_factor).round() as u32).max(1),
+    };
+    // the whole pipeline, so that matte and lossless settings apply too
+    let image = resized_binary_alpha(image, &Settings { resize, ..*settings })?;
+    let mut canvas = ImgVec::new(vec![RGBA8::new(0, 0, 0, 0); width * height], width, height);
+    blit_at(image.as_ref(), &mut canvas, (left * x_factor).round() as isize, (top * y_factor).round() as isize);
+    Ok(canvas)
+}
+
+/// Like `blit`, but the position can be outside of `dst`, and then the parts outside are cut off
+fn blit_at(src: ImgRef<RGBA8>, dst: &mut ImgVec<RGBA8>, left: isize, top: isize) {
+    blit(src, (left.min(0).unsigned_abs(), top.min(0).unsigned_abs()), dst, (left.max(0) as usize, top.max(0) as usize));
+}
+
+fn blit(src: ImgRef<RGBA8>, (src_left, src_top): (usize, usize), dst: &mut ImgVec<RGBA8>, (dst_left, dst_top): (usize, usize)) {
+    if src_left >= src.width() || src_top >= src.height() || dst_left >= dst.width() || dst_top >= dst.height() {
+        return;
+    }
+    let width = (src.width() - src_left).min(dst.width() - dst_left);
+    let height = (src.height() - src_top).min(dst.height() - dst_top);
+    let src = src.sub_image(src_left, src_top, width, height);
+    let mut dst = dst.sub_image_mut(dst_left, dst_top, width, height);
+    for (dst_row, src_row) in dst.rows_mut().zip(src.rows()) {
+        dst_row.copy_from_slice(src_row);
+    }
+}
+
+#[test]
+fn fit_policies() {
+    let red = RGBA8::new(255, 0, 0, 255);
+    let clear = RGBA8::new(0, 0, 0, 0);
+    let small = ImgVec::new(vec![red; 2 * 2], 2, 2);
+
+    let placed = place(small.as_ref(), 3, 1, 4, 4);
+    assert_eq!(red, placed[(3_usize, 2_usize)]);
+    assert_eq!(clear, placed[(2_usize, 1_usize)]);
+
+    let mut settings = Settings { frame_fit: FrameFit::Center, resize: Resize::None, ..Settings::default() };
+    let centered = fit(small.clone(), 4, 4, &settings).unwrap();
+    assert_eq!(&[clear, red, red, clear][..], centered.rows().nth(1).unwrap());
+
+    settings.frame_fit = FrameFit::TopLeft;
+    let top_left = fit(small.clone(), 4, 3, &settings).unwrap();
+    assert_eq!(&[red, red, clear, clear][..], top_left.rows().next().unwrap());
+    assert_eq!(&[clear; 4][..], top_left.rows().nth(2).unwrap());
+
+    settings.frame_fit = FrameFit::Scale;
+    let scaled = fit(small.clone(), 8, 4, &settings).unwrap();
+    assert_eq!((8, 4), (scaled.width(), scaled.height()));
+    assert_eq!(clear, scaled[(1_usize, 2_usize)]);
+    assert_eq!(255, scaled[(4_usize, 2_usize)].a);
+
+    let large = ImgVec::new(vec![red; 6 * 2], 6, 2);
+    settings.frame_fit = FrameFit::Center;
+    let cropped = fit(large, 4, 4, &settings).unwrap();
+    assert_eq!(&[red; 4][..], cropped.rows().nth(1).unwrap());
+
+    // fitted in the canvas first, and then resized like the canvas
+    settings.resize = Resize::Exact { width: 4, height: 4 };
+    settings.resample = Resample::Point;
+    let centered = fit(ImgVec::new(vec![red; 4 * 4], 4, 4), 8, 8, &settings).unwrap();
+    assert_eq!((4, 4), (centered.width(), centered.height()));
+    assert_eq!(&[clear; 4][..], centered.rows().next().unwrap());
+    assert_eq!(&[clear, red, red, clear][..], centered.rows().nth(1).unwrap());
+    settings.frame_fit = FrameFit::Scale;
+    let scaled = fit(ImgVec::new(vec![red; 4 * 2], 4, 2), 8, 8, &settings).unwrap();
+    assert_eq!((4, 4), (scaled.width(), scaled.height()));
+    assert_eq!(&[clear; 4][..], scaled.rows().next().unwrap());
+    assert_eq!(&[red; 4][..], scaled.rows().nth(1).unwrap());
+    assert_eq!(&[red; 4][..], scaled.rows().nth(2).unwrap());
+}
@@ -6,35 +6,70 @@
 pub use imgref::ImgVec;
 pub use rgb::{RGB8, RGBA8};
 
-use crate::error::GifResult;
+use crate::error::{CatResult, GifResult};
 use crossbeam_channel::Sender;
 
 #[cfg(feature = "png")]
 use std::path::PathBuf;
 
+#[derive(Clone)]
 pub(crate) enum FrameSource {
     Pixels(ImgVec<RGBA8>),
+    /// Drawn on a transparent canvas of the given size
+    Placed { image: ImgVec<RGBA8>, left: usize, top: usize, canvas_width: usize, canvas_height: usize },
     #[cfg(feature = "png")]
     PngData(Vec<u8>),
     #[cfg(all(feature = "png", not(target_arch = "wasm32")))]
     Path(PathBuf),
 }
 
+/// When to display the frame
+#[derive(Copy, Clone)]
+pub(crate) enum FrameTime {
+    /// Time in seconds when to display the frame. First frame should start at 0.
+    Pts(f64),
+    /// Time in seconds the frame is displayed for, starting when the previous frame ends.
+    Duration(f64),
+}
+
+#[derive(Clone)]
 pub(crate) struct InputFrame {
     /// The pixels to resize and encode
     pub frame: FrameSource,
-    /// Time in seconds when to display the frame. First frame should start at 0.
-    pub presentation_timestamp: f64,
+    pub time: FrameTime,
     pub frame_index: usize,
 }
 
+impl FrameSource {
+    /// Decodes PNG if necessary
+    pub(crate) fn into_pixels(self) -> CatResult<ImgVec<RGBA8>> {
+        Ok(match self {
+            Self::Pixels(image) => image,
+            Self::Placed { image, left, top, canvas_width, canvas_height } => {
+                crate::canvas::place(image.as_ref(), left, top, canvas_width, canvas_height)
+            },
+            #[cfg(feature = "png")]
+            Self::PngData(data) => {
+                let image = lodepng::decode32(&data)
+                    .map_err(|err| crate::Error::PNG(format!("Can't load PNG: {err}")))?;
+                ImgVec::new(image.buffer, image.width, image.height)
+            },
+            #[cfg(all(feature = "png", not(target_arch = "wasm32")))]
+            Self::Path(path) => {
+                let image = lodepng::decode32_file(&path)
+                    .map_err(|err| crate::Error::PNG(format!("Can't load {}: {err}", path.display())))?;
+                ImgVec::new(image.buffer, image.width, image.height)
+            },
+        })
+    }
+}
+
 pub(crate) struct InputFrameResized {
     /// The pixels to encode
     pub frame: ImgVec<RGBA8>,
     /// The same as above, but with smart blur applied (for denoiser)
     pub frame_blurred: ImgVec<RGB8>,
-    /// Time in seconds when to display the frame. First frame should start at 0.
-    pub presentation_timestamp: f64,
+    pub time: FrameTime,
 
     /// Debugging bad inputs
     pub original_index: usize,
@@ -57,6 +92,7 @@ impl Collector {
     /// Presentation timestamp is time in seconds (since file start at 0) when this frame is to be displayed.
     ///
     /// If the first frame doesn't start at pts=0, the delay will be used for the last frame.
+    /// For exact control over frame delays, see [`add_frame_rgba_with_duration`][Self::add_frame_rgba_with_duration].
     ///
     /// If this function appears to be stuck after a few frames, it's because [`crate::Writer::write()`] is not running.
     #[cfg_attr(debug_assertions, track_caller)]
@@ -65,7 +101,56 @@ impl Collector {
         self.queue.send(InputFrame {
             frame_index,
             frame: FrameSource::Pixels(frame),
-            presentation_timestamp,
+            time: FrameTime::Pts(presentation_timestamp),
+        })?;
+        Ok(())
+    }
+
+    /// Frame index starts at 0.
+    ///
+    /// Like [`add_frame_rgba`][Self::add_frame_rgba], but instead of a timestamp, it takes time in seconds
+    /// for how long this frame is to be displayed. The frame starts when the previous frame ends.
+    ///
+    /// The duration is rounded to whole centiseconds (GIF's unit of time), and then used exactly,
+    /// including for the last frame. Durations shorter than 0.01s are made 0.01s.
+    /// GIF and APNG can't show frames for less than 0.02s, so shorter frames are lengthened as [`Settings::timing`][crate::Settings::timing]
+    /// says: by default the extra time is taken from the following frames, and [`TimingMode::Strict`][crate::TimingMode::Strict] makes it an error.
+    ///
+    /// Use either durations or timestamps for all frames, not both.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn add_frame_rgba_with_duration(&self, frame_index: usize, frame: ImgVec<RGBA8>, duration: f64) -> GifResult<()> {
+        debug_assert!(duration > 0.);
+        self.queue.send(InputFrame {
+            frame_index,
+            frame: FrameSource::Pixels(frame),
+            time: FrameTime::Duration(duration),
+        })?;
+        Ok(())
+    }
+
+    /// Frame index starts at 0.
+    ///
+    /// Like [`add_frame_rgba`][Self::add_frame_rgba], but for frames that cover only a part of the animation,
+    /// such as sprites or regions of a screen recording. The frame is drawn at `left`,`top` on a transparent
+    /// canvas of `canvas_width`×`canvas_height` pixels. Parts outside of the canvas are cut off.
+    ///
+    /// The canvas should have the same size for all frames. If it doesn't, [`Settings::frame_fit`][crate::Settings::frame_fit] applies.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn add_frame_rgba_at(&self, frame_index: usize, frame: ImgVec<RGBA8>, (left, top): (u32, u32), (canvas_width, canvas_height): (u32, u32), presentation_timestamp: f64) -> GifResult<()> {
+        debug_assert!(frame_index == 0 || presentation_timestamp > 0.);
+        if canvas_width == 0 || canvas_height == 0 {
+            return Err(crate::Error::InvalidSettings("canvas can't be empty".into()));
+        }
+        self.queue.send(InputFrame {
+            frame_index,
+            frame: FrameSource::Placed {
+                image: frame,
+                left: left as usize,
+                top: top as usize,
+                canvas_width: canvas_width as usize,
+                canvas_height: canvas_height as usize,
+            },
+            time: FrameTime::Pts(presentation_timestamp),
         })?;
         Ok(())
     }
@@ -86,7 +171,7 @@ impl Collector {
     pub fn add_frame_png_data(&self, frame_index: usize, png_data: Vec<u8>, presentation_timestamp: f64) -> GifResult<()> {
         self.queue.send(InputFrame {
             frame: FrameSource::PngData(png_data),
-            presentation_timestamp,
+            time: FrameTime::Pts(presentation_timestamp),
             frame_index,
         })?;
         Ok(())
@@ -106,7 +191,37 @@ impl Collector {
     pub fn add_frame_png_file(&self, frame_index: usize, path: PathBuf, presentation_timestamp: f64) -> GifResult<()> {
         self.queue.send(InputFrame {
             frame: FrameSource::Path(path),
-            presentation_timestamp,
+            time: FrameTime::Pts(presentation_timestamp),
+            frame_index,
+        })?;
+        Ok(())
+    }
+
+    /// Like [`add_frame_png_data`][Self::add_frame_png_data], but with a duration in seconds instead of a timestamp.
+    ///
+    /// See [`add_frame_rgba_with_duration`][Self::add_frame_rgba_with_duration].
+    #[cfg(feature = "png")]
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn add_frame_png_data_with_duration(&self, frame_index: usize, png_data: Vec<u8>, duration: f64) -> GifResult<()> {
+        debug_assert!(duration > 0.);
+        self.queue.send(InputFrame {
+            frame: FrameSource::PngData(png_data),
+            time: FrameTime::Duration(duration),
+            frame_index,
+        })?;
+        Ok(())
+    }
+
+    /// Like [`add_frame_png_file`][Self::add_frame_png_file], but with a duration in seconds instead of a timestamp.
+    ///
+    /// See [`add_frame_rgba_with_duration`][Self::add_frame_rgba_with_duration].
+    #[cfg(feature = "png")]
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn add_frame_png_file_with_duration(&self, frame_index: usize, path: PathBuf, duration: f64) -> GifResult<()> {
+        debug_assert!(duration > 0.);
+        self.queue.send(InputFrame {
+            frame: FrameSource::Path(path),
+            time: FrameTime::Duration(duration),
             frame_index,
         })?;
         Ok(())
@@ -0,0 +1,104 @@
+//! Cropping of input frames, before they're resized
+
+use crate::error::CatResult;
+use crate::{Crop, Error, GifResult};
+use imgref::{ImgRef, ImgVec};
+use rgb::RGBA8;
+
+/// Auto-crop looks for borders in this many first frames
+pub(crate) const AUTO_CROP_SAMPLE_FRAMES: usize = 10;
+
+/// Per-channel difference still considered the same color, because video compression makes borders a bit noisy
+const BORDER_TOLERANCE: u8 = 8;
+
+impl Crop {
+    #[cold]
+    pub(crate) fn validate(self) -> GifResult<()> {
+        match self {
+            Self::Rect { width, height, .. } if width == 0 || height == 0 => Err(Error::InvalidSettings("crop area can't be empty".into())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Area (left, top, width, height) of a frame of this size. `Auto` has to be replaced with the result of `detect_borders` first, otherwise it's the whole frame.
+    pub(crate) fn area(self, image_width: usize, image_height: usize) -> CatResult<(usize, usize, usize, usize)> {
+        let Self::Rect { left, top, width, height } = self else {
+            return Ok((0, 0, image_width, image_height));
+        };
+        let (left, top) = (left as usize, top as usize);
+        if left >= image_width || top >= image_height {
+            return Err(Error::WrongSize(format!("crop area starts at {left},{top}, outside of the {image_width}×{image_height} frame")));
+        }
+        // frames may be smaller than the crop area
+        Ok((left, top, (width as usize).min(image_width - left), (height as usize).min(image_height - top)))
+    }
+
+    pub(crate) fn apply(self, image: ImgVec<RGBA8>) -> CatResult<ImgVec<RGBA8>> {
+        let (left, top, width, height) = self.area(image.width(), image.height())?;
+        if width == image.width() && height == image.height() {
+            return Ok(image);
+        }
+        let buf = image.sub_image(left, top, width, height).to_contiguous_buf().0.into_owned();
+        Ok(ImgVec::new(buf, width, height))
+    }
+}
+
+/// Finds borders of constant color that are the same in all the frames, such as letterboxing
+pub(crate) fn detect_borders(frames: &[ImgRef<'_, RGBA8>]) -> Crop {
+    let Some(first) = frames.first() else {
+        return Crop::None;
+    };
+    let (width, height) = (first.width(), first.height());
+    if width == 0 || height == 0 || frames.iter().any(|f| f.width() != width || f.height() != height) {
+        return Crop::None;
+    }
+
+    let is_filled = |left: usize, top: usize, w: usize, h: usize, color: RGBA8| {
+        frames.iter().all(|f| f.sub_image(left, top, w, h).pixels().all(|px| same_color(px, color)))
+    };
+    let top_left = first.buf()[0];
+    let bottom_right = first.buf()[(height - 1) * first.stride() + width - 1];
+
+    let top = (0..height).take_while(|&y| is_filled(0, y, width, 1, top_left)).count();
+    // a blank frame has no content to keep
+    if top == height {
+        return Crop::None;
+    }
+    let bottom = (top..height).rev().take_while(|&y| is_filled(0, y, width, 1, bottom_right)).count();
+    let inner_height = height - top - bottom;
+    let left = (0..width).take_while(|&x| is_filled(x, top, 1, inner_height, top_left)).count();
+    let right = (left..width).rev().take_while(|&x| is_filled(x, top, 1, inner_height, bottom_right)).count();
+    let inner_width = width - left - right;
+
+    if inner_width == 0 || (inner_width == width && inner_height == height) {
+        return Crop::None;
+    }
+    Crop::Rect { left: left as u32, top: top as u32, width: inner_width as u32, height: inner_height as u32 }
+}
+
+fn same_color(a: RGBA8, b: RGBA8) -> bool {
+    (a.a == 0 && b.a == 0) || (
+        a.r.abs_diff(b.r) <= BORDER_TOLERANCE &&
+        a.g.abs_diff(b.g) <= BORDER_TOLERANCE &&
+        a.b.abs_diff(b.b) <= BORDER_TOLERANCE &&
+        a.a.abs_diff(b.a) <= BORDER_TOLERANCE
+    )
+}
+
+#[test]
+fn letterbox() {
+    let black = RGBA8::new(2, 1, 0, 255);
+    let mut frame = ImgVec::new(vec![black; 10 * 8], 10, 8);
+    frame[(3_usize, 2_usize)] = RGBA8::new(200, 100, 50, 255);
+    frame[(6_usize, 5_usize)] = RGBA8::new(20, 200, 50, 255);
+    let mut frame2 = frame.clone();
+    frame2[(2_usize, 4_usize)] = RGBA8::new(0, 0, 255, 255);
+    assert_eq!(Crop::Rect { left: 2, top: 2, width: 5, height: 4 }, detect_borders(&[frame.as_ref(), frame2.as_ref()]));
+
+    let blank = ImgVec::new(vec![black; 10 * 8], 10, 8);
+    assert_eq!(Crop::None, detect_borders(&[blank.as_ref()]));
+
+    let cropped = Crop::Rect { left: 2, top: 2, width: 50, height: 4 }.apply(frame).unwrap();
+    assert_eq!((8, 4), (cropped.width(), cropped.height()));
+    assert_eq!(RGBA8::new(200, 100, 50, 255), cropped[(1_usize, 0_usize)]);
+}
@@ -9,9 +9,16 @@ pub use rgb::RGBA8;
 
 const LOOKAHEAD: usize = 5;
 
+/// `color_diff` of pixels that have clearly changed, about 40 per channel
+const SCENE_CUT_PIXEL_DIFF: u32 = 40 * 40 * 6;
+/// Percentage of pixels that have to change for it to be a different scene
+const SCENE_CUT_CHANGED_PERCENT: usize = 70;
+
 #[derive(Debug, Default, Copy, Clone)]
 pub struct Acc {
     px_blur: [(RGB8, RGB8); LOOKAHEAD],
+    /// Of the pixels in `px_blur`. Only WebP and truecolor APNG have alpha other than 0 and 255.
+    alphas: [u8; LOOKAHEAD],
     alpha_bits: u8,
     can_stay_for: u8,
     stayed_for: u8,
@@ -38,14 +45,16 @@ impl Acc {
     pub fn append(&mut self, val: RGBA8, val_blur: RGB8) {
         for n in 1..LOOKAHEAD {
             self.px_blur[n - 1] = self.px_blur[n];
+            self.alphas[n - 1] = self.alphas[n];
         }
         self.alpha_bits >>= 1;
 
-        if val.a < 128 {
+        if val.a == 0 {
             self.alpha_bits |= 1 << (LOOKAHEAD - 1);
         } else {
             self.px_blur[LOOKAHEAD - 1] = (val.rgb(), val_blur);
         }
+        self.alphas[LOOKAHEAD - 1] = val.a;
     }
 }
 
@@ -79,6 +88,7 @@ impl<T> Denoiser<T> {
         let area = width.checked_mul(height).ok_or(WrongSizeError)?;
         let clear = Acc {
             px_blur: [(RGB8::new(0, 0, 0), RGB8::new(0, 0, 0)); LOOKAHEAD],
+            alphas: [0; LOOKAHEAD],
             alpha_bits: (1 << LOOKAHEAD) - 1,
             bg_set: RGBA8::default(),
             stayed_for: 0,
@@ -93,6 +103,12 @@ impl<T> Denoiser<T> {
         })
     }
 
+    /// Pass pixels through unchanged. Only pixels identical to the previous frame get 0 in the importance map.
+    #[inline]
+    pub fn set_exact(&mut self) {
+        self.threshold = 0;
+    }
+
     fn quick_append(&mut self, frame: ImgRef<RGBA8>, frame_blurred: ImgRef<RGB8>) {
         for ((acc, src), src_blur) in self.splat.pixels_mut().zip(frame.pixels()).zip(frame_blurred.pixels()) {
             acc.append(src, src_blur);
@@ -177,11 +193,16 @@ impl<T> Denoiser<T> {
 
 impl Acc {
     fn next_pixel(&mut self, threshold: u32, odd_frame: bool) -> (RGBA8, u8) {
+        if threshold == 0 {
+            return self.next_pixel_exact();
+        }
         // No previous bg set, so find a new one
         if let Some((curr, curr_blur)) = self.get(0) {
+            let curr_alpha = self.alphas[0];
             let my_turn = cohort(curr) != odd_frame;
             let threshold = if my_turn { threshold } else { threshold * 2 };
-            let diff_with_bg = if self.bg_set.a > 0 {
+            // a change of alpha is never noise
+            let diff_with_bg = if self.bg_set.a == curr_alpha {
                 let bg = color_diff(self.bg_set.rgb(), curr);
                 let bg_blur = color_diff(self.bg_set.rgb(), curr_blur);
                 if bg < bg_blur { bg } else { (bg + bg_blur) / 2 }
@@ -213,7 +234,7 @@ impl Acc {
             // See how long this bg can stay
             let mut stays_frames = 0;
             for i in 1..LOOKAHEAD {
-                if self.get(i).is_some_and(|(c, blurred)| color_diff(c, curr) < threshold || color_diff(blurred, curr_blur) < threshold) {
+                if self.alphas[i] == curr_alpha && self.get(i).is_some_and(|(c, blurred)| color_diff(c, curr) < threshold || color_diff(blurred, curr_blur) < threshold) {
                     stays_frames = i;
                 } else {
                     break;
@@ -222,7 +243,7 @@ impl Acc {
 
             // fast path for regular changing pixel
             if stays_frames == 0 {
-                self.bg_set = curr.with_alpha(255);
+                self.bg_set = curr.with_alpha(curr_alpha);
                 return (self.bg_set, pixel_importance(diff_with_bg, threshold, 10, 110));
             }
             let imp = if stays_frames <= 1 {
@@ -234,7 +255,7 @@ impl Acc {
             };
 
             // set the new current (bg) color to the median of the frames it matches
-            self.bg_set = get_medians(&self.px_blur, stays_frames).with_alpha(255);
+            self.bg_set = get_medians(&self.px_blur, stays_frames).with_alpha(curr_alpha);
             // shorten stay-for to use overlapping ranges for smoother transitions
             self.can_stay_for = (stays_frames as u8).min(LOOKAHEAD as u8 - 1);
             self.stayed_for = 0;
@@ -250,6 +271,22 @@ impl Acc {
             (RGBA8::new(0,0,0,0), imp)
         }
     }
+
+    fn next_pixel_exact(&mut self) -> (RGBA8, u8) {
+        if let Some((curr, _)) = self.get(0) {
+            let curr = curr.with_alpha(self.alphas[0]);
+            if self.bg_set == curr {
+                return (curr, 0);
+            }
+            self.bg_set = curr;
+            (curr, 255)
+        } else {
+            // needs to clear the background, like in next_pixel
+            let imp = u8::from(self.bg_set.a > 0);
+            self.bg_set.a = 0;
+            (RGBA8::new(0, 0, 0, 0), imp)
+        }
+    }
 }
 
 /// Median of 9 neighboring pixels
@@ -396,6 +433,12 @@ fn color_diff(x: RGB8, y: RGB8) -> u32 {
     (x.b - y.b).pow(2) as u32
 }
 
+/// Compares blurred frames, so that noise and small movements don't count
+pub(crate) fn is_scene_cut(prev: ImgRef<RGB8>, curr: ImgRef<RGB8>) -> bool {
+    let changed = prev.pixels().zip(curr.pixels()).filter(|&(p, c)| color_diff(p, c) > SCENE_CUT_PIXEL_DIFF).count();
+    changed * 100 > prev.width() * prev.height() * SCENE_CUT_CHANGED_PERCENT
+}
+
 #[track_caller]
 #[cfg(test)]
 fn px<T>(f: Denoised<T>) -> (RGBA8, T) {
@@ -418,6 +461,27 @@ fn one() {
     assert!(matches!(d.pop(), Denoised::Done));
 }
 
+#[test]
+fn exact() {
+    let mut d = Denoiser::new(1, 1, 100).unwrap();
+    d.set_exact();
+    let a = RGBA8::new(100, 100, 100, 255);
+    let b = RGBA8::new(100, 100, 101, 255);
+    for (n, px) in [a, b, b, a].into_iter().enumerate() {
+        d.push_frame_test(ImgVec::new(vec![px], 1, 1).as_ref(), n).unwrap();
+    }
+    d.flush();
+    let imp = |f: Denoised<usize>| match f {
+        Denoised::Frame { frame, importance_map, .. } => (frame.pixels().next().unwrap(), importance_map.pixels().next().unwrap()),
+        _ => panic!("no frame"),
+    };
+    assert_eq!(imp(d.pop()), (a, 255));
+    assert_eq!(imp(d.pop()), (b, 255));
+    assert_eq!(imp(d.pop()), (b, 0));
+    assert_eq!(imp(d.pop()), (a, 255));
+    assert!(matches!(d.pop(), Denoised::Done));
+}
+
 #[test]
 fn two() {
     let mut d = Denoiser::new(1,1, 100).unwrap();
@@ -542,3 +606,15 @@ fn many() {
     assert_eq!(px(d.pop()), (b, "b6"));
     assert!(matches!(d.pop(), Denoised::Done));
 }
+
+#[test]
+fn scene_cut() {
+    let dark = ImgVec::new(vec![RGB8::new(10, 20, 30); 10 * 10], 10, 10);
+    let mut moved = dark.clone();
+    moved.pixels_mut().take(50).for_each(|px| *px = RGB8::new(250, 250, 250));
+    assert!(!is_scene_cut(dark.as_ref(), moved.as_ref()));
+    let noisy = ImgVec::new(dark.pixels().map(|px| px.map(|c| c + 15)).collect(), 10, 10);
+    assert!(!is_scene_cut(dark.as_ref(), noisy.as_ref()));
+    let bright = ImgVec::new(vec![RGB8::new(200, 180, 30); 10 * 10], 10, 10);
+    assert!(is_scene_cut(dark.as_ref(), bright.as_ref()));
+}
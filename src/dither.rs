@@ -0,0 +1,108 @@
+//! Ordered dithering, which gives the same pattern for the same colors in every frame
+
+use crate::PushInCapacity;
+use imgref::{ImgRef, ImgVec};
+use rgb::RGBA8;
+use std::collections::HashMap;
+
+/// Distance between the darkest and the lightest threshold of the pattern at full dithering level
+const DITHER_SPREAD: f32 = 24.;
+
+/// Adds a transparent color to the palette if the image needs one, and the palette doesn't have it.
+///
+/// Returns `None` if there's no room for it.
+pub(crate) fn with_transparent_color(mut pal: Vec<RGBA8>, image: ImgRef<RGBA8>) -> Option<Vec<RGBA8>> {
+    if !pal.iter().any(|c| c.a == 0) && image.pixels().any(|px| px.a < 128) {
+        if pal.len() >= 256 {
+            return None;
+        }
+        pal.push(RGBA8::new(0, 0, 0, 0));
+    }
+    Some(pal)
+}
+
+/// Maps colors to the palette, offsetting them by a pattern that depends only on pixel position.
+/// Pixels that end up with the same color as the background become transparent.
+///
+/// The palette must have a transparent color if the image has transparent pixels, see [`with_transparent_color`].
+pub(crate) fn remap_ordered(image: ImgRef<RGBA8>, pal: Vec<RGBA8>, background: Option<ImgRef<RGBA8>>, dithering_level: f32) -> (ImgVec<u8>, Vec<RGBA8>) {
+    let transparent_index = pal.iter().position(|c| c.a == 0).map(|i| i as u8);
+    let spread = DITHER_SPREAD * dithering_level;
+    let mut nearest_cache = HashMap::new();
+    let mut nearest = |color: [i16; 3]| -> u8 {
+        *nearest_cache.entry(color).or_insert_with(|| {
+            pal.iter().enumerate().filter(|(_, c)| c.a > 0).min_by_key(|(_, c)| {
+                let diff = |a: u8, b: i16| i32::from(i16::from(a) - b).pow(2);
+                diff(c.r, color[0]) + diff(c.g, color[1]) + diff(c.b, color[2])
+            }).map_or(0, |(idx, _)| idx as u8)
+        })
+    };
+
+    let mut out = Vec::with_capacity(image.width() * image.height());
+    let mut bg_rows = background.map(|bg| bg.rows());
+    for (y, row) in image.rows().enumerate() {
+        let bg_row = bg_rows.as_mut().and_then(|rows| rows.next());
+        for (x, &px) in row.iter().enumerate() {
+            if px.a < 128 {
+                debug_assert!(transparent_index.is_some());
+                out.push_in_cap(transparent_index.unwrap_or(0));
+                continue;
+            }
+            let offset = ((f32::from(bayer8(x, y)) + 0.5) / 64. - 0.5) * spread;
+            let offset = |c: u8| (f32::from(c) + offset).round() as i16;
+            let idx = nearest([offset(px.r), offset(px.g), offset(px.b)]);
+            let on_screen = bg_row.is_some_and(|bg_row| {
+                let bg = bg_row[x];
+                bg.a > 0 && pal.get(idx as usize).is_some_and(|&c| c == bg)
+            });
+            out.push_in_cap(match transparent_index {
+                Some(transparent_index) if on_screen => transparent_index,
+                _ => idx,
+            });
+        }
+    }
+    (ImgVec::new(out, image.width(), image.height()), pal)
+}
+
+/// 0-63 threshold of an 8×8 Bayer matrix
+#[inline]
+fn bayer8(x: usize, y: usize) -> u8 {
+    let xc = x ^ y;
+    (((xc & 1) << 5) | ((y & 1) << 4) | ((xc & 2) << 2) | ((y & 2) << 1) | ((xc & 4) >> 1) | ((y & 4) >> 2)) as u8
+}
+
+#[test]
+fn stable_pattern() {
+    let mut all: Vec<_> = (0..8).flat_map(|y| (0..8).map(move |x| bayer8(x, y))).collect();
+    all.sort_unstable();
+    assert_eq!(all, (0..64).collect::<Vec<u8>>());
+
+    let grey = RGBA8::new(128, 128, 128, 255);
+    let pal = vec![RGBA8::new(0, 0, 0, 0), RGBA8::new(120, 120, 120, 255), RGBA8::new(136, 136, 136, 255)];
+    let image = ImgVec::new(vec![grey; 16 * 8], 16, 8);
+    let (remapped, _) = remap_ordered(image.as_ref(), pal.clone(), None, 1.);
+    assert!(remapped.pixels().any(|i| i == 1) && remapped.pixels().any(|i| i == 2));
+    // the pattern repeats, regardless of where the area is
+    assert_eq!(remapped.sub_image(0, 0, 8, 8).pixels().collect::<Vec<_>>(), remapped.sub_image(8, 0, 8, 8).pixels().collect::<Vec<_>>());
+
+    // reuses what's already on screen
+    let (on_bg, _) = remap_ordered(image.as_ref(), pal.clone(), Some(ImgRef::new(&[pal[1]; 16 * 8], 16, 8)), 1.);
+    for (bg, px) in on_bg.pixels().zip(remapped.pixels()) {
+        assert_eq!(if px == 1 { 0 } else { px }, bg);
+    }
+}
+
+#[test]
+fn transparent_slot() {
+    let red = RGBA8::new(255, 0, 0, 255);
+    let mut pixels = vec![red; 8];
+    pixels[3].a = 0;
+    let image = ImgRef::new(&pixels, 8, 1);
+    let pal = with_transparent_color(vec![red], image).unwrap();
+    assert_eq!(pal, [red, RGBA8::new(0, 0, 0, 0)]);
+    let (remapped, _) = remap_ordered(image, pal, None, 1.);
+    assert_eq!(remapped.buf()[..4], [0, 0, 0, 1]);
+    assert!(with_transparent_color(vec![red; 256], image).is_none());
+    // opaque images don't need the slot
+    assert_eq!(with_transparent_color(vec![red; 256], ImgRef::new(&[red; 8], 8, 1)).unwrap().len(), 256);
+}
@@ -0,0 +1,301 @@
+use crate::encoderust::CountingWriter;
+use crate::error::CatResult;
+use crate::truecolor::TruecolorEncoder;
+use crate::{Error, FrameEncoder, GIFFrame, Repeat, Settings};
+use gif::DisposalMethod;
+use apng::chunk::{self, ChunkType};
+use apng::{BitDepth, BlendOp, ColorType, Compression, DisposeOp, FrameControl};
+use imgref::{ImgRef, ImgVec};
+use rgb::{RGB8, RGBA8};
+use std::cell::Cell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// A frame compressed on its own, waiting for its sequence number
+pub(crate) struct ApngFrame {
+    left: u16,
+    top: u16,
+    width: u32,
+    height: u32,
+    dispose: DisposalMethod,
+    /// Zlib stream from IDAT chunks
+    data: Vec<u8>,
+    /// Only for palette frames. All frames share the same palette.
+    pal: Option<(Vec<RGB8>, Option<u8>)>,
+}
+
+/// Writes APNG using gifski's GIF frames with the global palette, or RGBA frames that haven't been quantized.
+///
+/// The acTL chunk needs number of frames, so everything is written in `finish()`.
+pub(crate) struct ApngEncoder<W: Write> {
+    writer: CountingWriter<W>,
+    frames: Vec<(ApngFrame, u16)>,
+    /// Compressed data of the buffered frames
+    frames_len: u64,
+    screen_width: u32,
+    screen_height: u32,
+}
+
+impl<W: Write> FrameEncoder<W> for ApngEncoder<W> {
+    type Frame = ApngFrame;
+
+    fn new(writer: W, written: Rc<Cell<u64>>) -> Self {
+        Self {
+            writer: CountingWriter { writer, written },
+            frames: Vec::new(),
+            frames_len: 0,
+            screen_width: 0,
+            screen_height: 0,
+        }
+    }
+
+    #[inline(never)]
+    fn compress_frame(f: GIFFrame, settings: &Settings) -> CatResult<ApngFrame> {
+        let GIFFrame {left, top, pal, image, dispose, transparent_index} = f;
+        // PNG has only one PLTE, so without the global palette frames aren't quantized at all
+        debug_assert!(settings.global_palette);
+        let (width, height) = (image.width() as u32, image.height() as u32);
+        let data = compress(image.into_contiguous_buf().0, width, height, Some((&pal, transparent_index)), settings)?;
+        Ok(ApngFrame {
+            left, top, width, height, dispose, data,
+            pal: Some((pal, transparent_index)),
+        })
+    }
+
+    /// The first frame is also the image for apps that don't support APNG, so it has to cover the whole canvas
+    fn compress_first_frame(f: GIFFrame, screen_width: u16, screen_height: u16, settings: &Settings) -> CatResult<ApngFrame> {
+        let (width, height) = (usize::from(screen_width), usize::from(screen_height));
+        if f.left == 0 && f.top == 0 && f.image.width() == width && f.image.height() == height {
+            return <Self as FrameEncoder<W>>::compress_frame(f, settings);
+        }
+        let GIFFrame {left, top, mut pal, image, dispose, transparent_index} = f;
+        let transparent_index = match transparent_index {
+            Some(t) => Some(t),
+            None if pal.len() < 256 => {
+                pal.push(RGB8::default());
+                Some((pal.len() - 1) as u8)
+            },
+            // a color that the image doesn't use can become the transparent one
+            None => {
+                let mut used = [false; 256];
+                image.pixels().for_each(|px| used[usize::from(px)] = true);
+                used.iter().position(|&u| !u).map(|i| {
+                    pal[i] = RGB8::default();
+                    i as u8
+                })
+            },
+        };
+        // with every color in use, the padding is opaque. It's only the first of subframes shown without delay,
+        // and the next ones are drawn over it.
+        let mut padded = ImgVec::new(vec![transparent_index.unwrap_or(0); width * height], width, height);
+        for (dst, src) in padded.rows_mut().skip(top.into()).zip(image.rows()) {
+            if let Some(dst) = dst.get_mut(usize::from(left)..usize::from(left) + src.len()) {
+                dst.copy_from_slice(src);
+            }
+        }
+        <Self as FrameEncoder<W>>::compress_frame(GIFFrame { left: 0, top: 0, pal, image: padded, dispose, transparent_index }, settings)
+    }
+
+    fn write_frame(&mut self, frame: ApngFrame, delay: u16, screen_width: u16, screen_height: u16, _: &Settings) -> CatResult<()> {
+        self.screen_width = screen_width.into();
+        self.screen_height = screen_height.into();
+        self.push(frame, delay);
+        Ok(())
+    }
+
+    fn finish(&mut self, settings: &Settings) -> CatResult<()> {
+        self.write_file(settings)
+    }
+}
+
+impl<W: Write> TruecolorEncoder<W> for ApngEncoder<W> {
+    type Frame = ApngFrame;
+    // the same as the palette frames
+    const UNITS_PER_SECOND: u32 = 100;
+    const MIN_DELAY: u64 = 2;
+    const MAX_DELAY: u64 = 30000;
+    const EVEN_OFFSETS: bool = false;
+
+    fn new(writer: W) -> Self {
+        <Self as FrameEncoder<W>>::new(writer, Rc::default())
+    }
+
+    fn compress_frame(left: usize, top: usize, image: ImgRef<RGBA8>, settings: &Settings) -> CatResult<ApngFrame> {
+        let (buf, width, height) = image.to_contiguous_buf();
+        let (width, height) = (width as u32, height as u32);
+        let data = compress(rgb::bytemuck::cast_slice(&buf).to_vec(), width, height, None, settings)?;
+        Ok(ApngFrame {
+            left: left as u16,
+            top: top as u16,
+            width, height, data,
+            dispose: DisposalMethod::Keep,
+            pal: None,
+        })
+    }
+
+    fn write_frame(&mut self, frame: ApngFrame, delay: u64, _: &Settings) -> CatResult<()> {
+        if self.frames.is_empty() {
+            self.screen_width = frame.width;
+            self.screen_height = frame.height;
+        }
+        self.push(frame, delay as u16);
+        Ok(())
+    }
+
+    fn written_bytes(&self) -> u64 {
+        self.writer.written.get() + self.frames_len
+    }
+
+    fn finish(&mut self, settings: &Settings) -> CatResult<()> {
+        self.write_file(settings)
+    }
+}
+
+impl<W: Write> ApngEncoder<W> {
+    fn push(&mut self, frame: ApngFrame, delay: u16) {
+        self.frames_len += frame.data.len() as u64;
+        self.frames.push((frame, delay));
+    }
+
+    fn write_file(&mut self, settings: &Settings) -> CatResult<()> {
+        let Some((first, _)) = self.frames.first() else {
+            return Ok(());
+        };
+        // the first frame is the default image, which must cover the whole canvas
+        debug_assert!(first.left == 0 && first.top == 0 && first.width == self.screen_width && first.height == self.screen_height);
+
+        let mut enc = apng::Encoder::new(&mut self.writer, self.screen_width, self.screen_height);
+        enc.set_depth(BitDepth::Eight);
+        match &first.pal {
+            Some((pal, transparent_index)) => {
+                enc.set_color(ColorType::Indexed);
+                enc.set_palette(rgb::bytemuck::cast_slice(pal).to_vec());
+                if let Some(tr) = *transparent_index {
+                    enc.set_trns((0..=tr).map(|i| if i == tr { 0 } else { 255 }).collect::<Vec<u8>>());
+                }
+            },
+            None => enc.set_color(ColorType::Rgba),
+        }
+        let num_plays = match settings.repeat {
+            Repeat::Infinite => 0,
+            Repeat::Finite(n) => u32::from(n) + 1,
+        };
+        enc.set_animated(self.frames.len() as u32, num_plays).map_err(png_error)?;
+        let mut w = enc.write_header().map_err(png_error)?;
+
+        let mut sequence_number = 0;
+        for (i, (frame, delay)) in self.frames.drain(..).enumerate() {
+            let mut fctl = Vec::with_capacity(26);
+            FrameControl {
+                sequence_number,
+                width: frame.width,
+                height: frame.height,
+                x_offset: frame.left.into(),
+                y_offset: frame.top.into(),
+                delay_num: delay,
+                delay_den: 100,
+                dispose_op: match frame.dispose {
+                    DisposalMethod::Background => DisposeOp::Background,
+                    DisposalMethod::Previous => DisposeOp::Previous,
+                    DisposalMethod::Keep | DisposalMethod::Any => DisposeOp::None,
+                },
+                // GIF's transparent pixels keep what was under them, and RGBA frames have all pixels of their area
+                blend_op: if frame.pal.is_some() { BlendOp::Over } else { BlendOp::Source },
+            }.encode(&mut fctl).map_err(png_error)?;
+            // encode() makes a whole chunk, but write_chunk needs only its data
+            w.write_chunk(chunk::fcTL, &fctl[8..fctl.len() - 4]).map_err(png_error)?;
+            sequence_number += 1;
+
+            if i == 0 {
+                w.write_chunk(chunk::IDAT, &frame.data).map_err(png_error)?;
+            } else {
+                let mut fdat = Vec::with_capacity(4 + frame.data.len());
+                fdat.extend_from_slice(&sequence_number.to_be_bytes());
+                fdat.extend_from_slice(&frame.data);
+                w.write_chunk(chunk::fdAT, &fdat).map_err(png_error)?;
+                sequence_number += 1;
+            }
+        }
+        w.finish().map_err(png_error)?;
+        Ok(())
+    }
+}
+
+/// Encoded as a standalone PNG, so that frames can be compressed in parallel
+fn compress(pixels: Vec<u8>, width: u32, height: u32, pal: Option<(&[RGB8], Option<u8>)>, settings: &Settings) -> CatResult<Vec<u8>> {
+    let mut png_file = Vec::new();
+    let mut enc = apng::Encoder::new(&mut png_file, width, height);
+    enc.set_depth(BitDepth::Eight);
+    enc.set_compression(if settings.fast { Compression::Fast } else { Compression::Best });
+    match pal {
+        Some((pal, transparent_index)) => {
+            enc.set_color(ColorType::Indexed);
+            enc.set_filter(apng::FilterType::NoFilter);
+            enc.set_palette(rgb::bytemuck::cast_slice(pal).to_vec());
+            if let Some(tr) = transparent_index {
+                enc.set_trns((0..=tr).map(|i| if i == tr { 0 } else { 255 }).collect::<Vec<u8>>());
+            }
+        },
+        None => {
+            enc.set_color(ColorType::Rgba);
+            enc.set_adaptive_filter(apng::AdaptiveFilterType::Adaptive);
+        },
+    }
+    let mut w = enc.write_header().map_err(png_error)?;
+    w.write_image_data(&pixels).map_err(png_error)?;
+    w.finish().map_err(png_error)?;
+    idat_data(&png_file)
+}
+
+/// Concatenated contents of all IDAT chunks
+fn idat_data(png_file: &[u8]) -> CatResult<Vec<u8>> {
+    let bad = || Error::PNG("Internal error; bad APNG frame".into());
+    let mut data = Vec::new();
+    let mut rest = png_file.get(8..).ok_or_else(bad)?;
+    while rest.len() >= 8 {
+        let (header, chunks) = rest.split_at(8);
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if ChunkType([header[4], header[5], header[6], header[7]]) == chunk::IDAT {
+            data.extend_from_slice(chunks.get(..len).ok_or_else(bad)?);
+        }
+        // data and crc
+        rest = chunks.get(len + 4..).ok_or_else(bad)?;
+    }
+    Ok(data)
+}
+
+#[cold]
+fn png_error(err: apng::EncodingError) -> Error {
+    match err {
+        apng::EncodingError::IoError(err) => err.into(),
+        other => Error::PNG(format!("APNG encoding error: {other}")),
+    }
+}
+
+#[test]
+fn first_frame_is_padded() {
+    let settings = Settings { global_palette: true, ..Settings::default() };
+    let f = GIFFrame {
+        left: 1, top: 2,
+        image: ImgVec::new(vec![0; 2 * 2], 2, 2),
+        pal: vec![RGB8::new(255, 0, 0)],
+        dispose: DisposalMethod::Keep,
+        transparent_index: None,
+    };
+    let frame = <ApngEncoder<Vec<u8>> as FrameEncoder<Vec<u8>>>::compress_first_frame(f, 4, 5, &settings).unwrap();
+    assert_eq!((0, 0, 4, 5), (frame.left, frame.top, frame.width, frame.height));
+    // a transparent color has been added for the padding
+    assert_eq!(Some((vec![RGB8::new(255, 0, 0), RGB8::default()], Some(1))), frame.pal);
+
+    // in a full palette, a color the image doesn't use becomes the transparent one
+    let f = GIFFrame {
+        left: 1, top: 2,
+        image: ImgVec::new(vec![0, 1, 3, 0], 2, 2),
+        pal: vec![RGB8::new(255, 0, 0); 256],
+        dispose: DisposalMethod::Keep,
+        transparent_index: None,
+    };
+    let frame = <ApngEncoder<Vec<u8>> as FrameEncoder<Vec<u8>>>::compress_first_frame(f, 4, 5, &settings).unwrap();
+    let (pal, transparent_index) = frame.pal.unwrap();
+    assert_eq!((RGB8::default(), Some(2)), (pal[2], transparent_index));
+}
@@ -1,5 +1,5 @@
 use crate::error::CatResult;
-use crate::{GIFFrame, Settings, SettingsExt};
+use crate::{FrameEncoder, GIFFrame, Settings};
 use rgb::RGB8;
 use std::cell::Cell;
 use std::io::Write;
@@ -9,9 +9,9 @@ use std::rc::Rc;
 #[cfg(feature = "gifsicle")]
 use crate::gifsicle;
 
-struct CountingWriter<W> {
-    writer: W,
-    written: Rc<Cell<u64>>,
+pub(crate) struct CountingWriter<W> {
+    pub writer: W,
+    pub written: Rc<Cell<u64>>,
 }
 
 impl<W: Write> Write for CountingWriter<W> {
@@ -32,22 +32,25 @@ pub(crate) struct RustEncoder<W: Write> {
     writer: Option<W>,
     written: Rc<Cell<u64>>,
     gif_enc: Option<gif::Encoder<CountingWriter<W>>>,
+    /// Palette of the first frame, which is in the header. Later frames with the same palette don't need their own.
+    global_pal: Option<Vec<u8>>,
 }
 
-impl<W: Write> RustEncoder<W> {
-    pub fn new(writer: W, written: Rc<Cell<u64>>) -> Self {
+impl<W: Write> FrameEncoder<W> for RustEncoder<W> {
+    type Frame = gif::Frame<'static>;
+
+    fn new(writer: W, written: Rc<Cell<u64>>) -> Self {
         Self {
             written,
             writer: Some(writer),
             gif_enc: None,
+            global_pal: None,
         }
     }
-}
 
-impl<W: Write> RustEncoder<W> {
     #[inline(never)]
     #[cfg_attr(debug_assertions, track_caller)]
-    pub fn compress_frame(f: GIFFrame, settings: &SettingsExt) -> CatResult<gif::Frame<'static>> {
+    fn compress_frame(f: GIFFrame, settings: &Settings) -> CatResult<gif::Frame<'static>> {
         let GIFFrame {left, top, pal, image, dispose, transparent_index} = f;
 
         let (buffer, width, height) = image.into_contiguous_buf();
@@ -68,65 +71,108 @@ impl<W: Write> RustEncoder<W> {
             left,
             width: width as u16,
             height: height as u16,
-            interlaced: false,
+            interlaced: settings.interlaced,
             palette: Some(pal_rgb),
             buffer: buffer.into(),
         };
 
-        #[allow(unused)]
-        let loss = settings.gifsicle_loss();
-        #[cfg(feature = "gifsicle")]
-        if loss > 0 {
-            Self::compress_gifsicle(&mut frame, loss)?;
-            return Ok(frame);
-        }
-
-        frame.make_lzw_pre_encoded();
+        compress_pixels(&mut frame, &pal, settings.gifsicle_loss())?;
         Ok(frame)
     }
 
-    #[cfg(feature = "gifsicle")]
-    #[inline(never)]
-    fn compress_gifsicle(frame: &mut gif::Frame<'static>, loss: u32) -> CatResult<()> {
-        use crate::Error;
-        use gifsicle::{GiflossyImage, GiflossyWriter};
-
-        let pal = frame.palette.as_ref().ok_or(Error::Gifsicle)?;
-        let g_pal = pal.chunks_exact(3)
-            .map(|c| RGB8 {
-                r: c[0],
-                g: c[1],
-                b: c[2],
-            })
-            .collect::<Vec<_>>();
-
-        let gif_img = GiflossyImage::new(&frame.buffer, frame.width, frame.height, frame.transparent, Some(&g_pal));
-
-        let mut lossy_writer = GiflossyWriter { loss };
-
-        frame.buffer = lossy_writer.write(&gif_img, None)?.into();
-        Ok(())
-    }
-
-    pub fn write_frame(&mut self, mut frame: gif::Frame<'static>, delay: u16, screen_width: u16, screen_height: u16, settings: &Settings) -> CatResult<()> {
+    fn write_frame(&mut self, mut frame: gif::Frame<'static>, delay: u16, screen_width: u16, screen_height: u16, settings: &Settings) -> CatResult<()> {
         frame.delay = delay; // the delay wasn't known
 
         let writer = &mut self.writer;
         let enc = match self.gif_enc {
             None => {
+                // the first frame's palette goes to the header. With `global_palette` all frames have the same palette,
+                // and otherwise frames that reuse a palette may have it too.
+                let global_pal = self.global_pal.insert(frame.palette.take().unwrap_or_default());
                 let w = CountingWriter {
                     writer: writer.take().ok_or(crate::Error::ThreadSend)?,
                     written: self.written.clone(),
                 };
-                let mut enc = gif::Encoder::new(w, screen_width, screen_height, &[])?;
+                let mut enc = gif::Encoder::new(w, screen_width, screen_height, global_pal)?;
                 enc.write_extension(gif::ExtensionData::Repetitions(settings.repeat))?;
                 enc.write_raw_extension(gif::Extension::Comment.into(), &[b"gif.ski"])?;
                 self.gif_enc.get_or_insert(enc)
             },
-            Some(ref mut enc) => enc,
+            Some(ref mut enc) => {
+                if frame.palette.is_some() && frame.palette == self.global_pal {
+                    frame.palette = None;
+                }
+                enc
+            },
         };
 
         enc.write_lzw_pre_encoded_frame(&frame)?;
         Ok(())
     }
+
+    fn finish(&mut self, _: &Settings) -> CatResult<()> {
+        // the trailer would be written on drop too, but without reporting errors
+        if let Some(enc) = self.gif_enc.take() {
+            enc.into_inner()?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the frame's pixels, in the normal row order, with LZW-compressed data, lossy if `loss` > 0
+#[cfg_attr(not(feature = "gifsicle"), allow(unused_variables))]
+#[inline(never)]
+pub(crate) fn compress_pixels(frame: &mut gif::Frame<'static>, pal: &[RGB8], loss: u32) -> CatResult<()> {
+    #[cfg(feature = "gifsicle")]
+    if loss > 0 {
+        use gifsicle::{GiflossyImage, GiflossyWriter};
+
+        // gifsicle reads rows in the interlaced order itself
+        let gif_img = GiflossyImage::new(&frame.buffer, frame.width, frame.height, frame.interlaced, frame.transparent, Some(pal));
+        frame.buffer = GiflossyWriter { loss }.write(&gif_img, None)?.into();
+        return Ok(());
+    }
+
+    if frame.interlaced {
+        frame.buffer = interlace_rows(&frame.buffer, frame.width.into()).into();
+    }
+    frame.make_lzw_pre_encoded();
+    Ok(())
+}
+
+/// Rows in the order they're stored in an interlaced GIF
+pub(crate) fn interlace_rows(buffer: &[u8], width: usize) -> Vec<u8> {
+    let height = buffer.len() / width.max(1);
+    (0..height).flat_map(|line| {
+        let y = interlaced_line(line, height);
+        &buffer[y * width..(y + 1) * width]
+    }).copied().collect()
+}
+
+/// Which row of the image is `line`-th in the interlaced order: every 8th row from 0, then every 8th from 4, every 4th from 2, and every 2nd from 1
+pub(crate) fn interlaced_line(line: usize, height: usize) -> usize {
+    let pass1 = height.div_ceil(8);
+    let pass2 = pass1 + (height + 3) / 8;
+    let pass3 = pass2 + (height + 1) / 4;
+    if line < pass1 {
+        line * 8
+    } else if line < pass2 {
+        (line - pass1) * 8 + 4
+    } else if line < pass3 {
+        (line - pass2) * 4 + 2
+    } else {
+        (line - pass3) * 2 + 1
+    }
+}
+
+#[test]
+fn interlaced_order() {
+    assert_eq!([0, 4, 2, 6, 1, 3, 5, 7], std::array::from_fn(|line| interlaced_line(line, 8)));
+    assert_eq!([0, 2, 1], std::array::from_fn(|line| interlaced_line(line, 3)));
+    for height in 1..40 {
+        let mut rows: Vec<_> = (0..height).map(|line| interlaced_line(line, height)).collect();
+        rows.sort_unstable();
+        assert_eq!(rows, (0..height).collect::<Vec<_>>());
+    }
+    assert_eq!(interlace_rows(&[0, 0, 1, 1, 2, 2], 2), [0, 0, 2, 2, 1, 1]);
 }
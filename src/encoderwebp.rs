@@ -0,0 +1,135 @@
+//! Animated WebP output. Frames are taken straight from the denoiser, without quantization.
+
+use crate::error::CatResult;
+use crate::truecolor::TruecolorEncoder;
+use crate::{Error, Repeat, Settings};
+use image_webp::{ColorType, WebPEncoder};
+use imgref::ImgRef;
+use rgb::RGBA8;
+use std::io::Write;
+
+/// WebP's limit
+const MAX_DIMENSION: usize = 16384;
+/// ANMF duration is 24-bit
+const MAX_DURATION_MS: u64 = (1 << 24) - 1;
+/// RIFF header, VP8X and ANIM chunks
+const HEADER_LEN: usize = 12 + 8 + 10 + 8 + 6;
+
+/// Buffers ANMF chunks, because the RIFF header needs size of the whole file
+pub(crate) struct AnimatedWebP<W: Write> {
+    writer: W,
+    frames_data: Vec<u8>,
+    canvas_size: Option<(usize, usize)>,
+}
+
+/// ANMF chunk's position, size and VP8L chunk, with its header
+pub(crate) struct WebPFrame {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+    vp8l: Vec<u8>,
+}
+
+impl<W: Write> TruecolorEncoder<W> for AnimatedWebP<W> {
+    type Frame = WebPFrame;
+    const UNITS_PER_SECOND: u32 = 1000;
+    // unlike GIF, there's no minimum, but frames without duration wouldn't be shown at all
+    const MIN_DELAY: u64 = 1;
+    const MAX_DELAY: u64 = MAX_DURATION_MS;
+    const EVEN_OFFSETS: bool = true;
+
+    fn new(writer: W) -> Self {
+        Self { writer, frames_data: Vec::new(), canvas_size: None }
+    }
+
+    fn compress_frame(left: usize, top: usize, image: ImgRef<RGBA8>, _: &Settings) -> CatResult<WebPFrame> {
+        Ok(WebPFrame { left, top, width: image.width(), height: image.height(), vp8l: encode_vp8l(image)? })
+    }
+
+    fn write_frame(&mut self, WebPFrame { left, top, width, height, vp8l }: WebPFrame, duration: u64, _: &Settings) -> CatResult<()> {
+        let (canvas_width, canvas_height) = *self.canvas_size.get_or_insert((width, height));
+        if canvas_width > MAX_DIMENSION || canvas_height > MAX_DIMENSION {
+            return Err(Error::WrongSize(format!("WebP can't be larger than {MAX_DIMENSION}×{MAX_DIMENSION}")));
+        }
+
+        let mut anmf = Vec::with_capacity(16 + vp8l.len());
+        // offsets are stored divided by 2
+        anmf.extend_from_slice(&u24(left / 2));
+        anmf.extend_from_slice(&u24(top / 2));
+        anmf.extend_from_slice(&u24(width - 1));
+        anmf.extend_from_slice(&u24(height - 1));
+        anmf.extend_from_slice(&u24(duration as usize));
+        // no blending, because the frame has all pixels of its area. no disposal.
+        anmf.push(0b10);
+        anmf.extend_from_slice(&vp8l);
+        write_chunk(&mut self.frames_data, b"ANMF", &anmf);
+        Ok(())
+    }
+
+    fn written_bytes(&self) -> u64 {
+        (HEADER_LEN + self.frames_data.len()) as u64
+    }
+
+    fn finish(&mut self, settings: &Settings) -> CatResult<()> {
+        let Some((canvas_width, canvas_height)) = self.canvas_size else {
+            return Err(Error::NoFrames);
+        };
+
+        let mut vp8x = [0; 10];
+        vp8x[0] = (1 << 4) | (1 << 1); // alpha, animation
+        vp8x[4..7].copy_from_slice(&u24(canvas_width - 1));
+        vp8x[7..10].copy_from_slice(&u24(canvas_height - 1));
+
+        let mut anim = [0; 6];
+        // background color is ignored by browsers; loop count 0 is infinite
+        anim[4..6].copy_from_slice(&match settings.repeat {
+            Repeat::Infinite => 0,
+            Repeat::Finite(n) => n.saturating_add(1),
+        }.to_le_bytes());
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&((HEADER_LEN - 8 + self.frames_data.len()) as u32).to_le_bytes());
+        header.extend_from_slice(b"WEBP");
+        write_chunk(&mut header, b"VP8X", &vp8x);
+        write_chunk(&mut header, b"ANIM", &anim);
+        debug_assert_eq!(HEADER_LEN, header.len());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.frames_data)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// image-webp makes a whole file, and this takes the VP8L chunk out of it
+fn encode_vp8l(image: ImgRef<RGBA8>) -> CatResult<Vec<u8>> {
+    let (buf, width, height) = image.to_contiguous_buf();
+    let mut webp_file = Vec::new();
+    WebPEncoder::new(&mut webp_file)
+        .encode(rgb::bytemuck::cast_slice(&buf), width as u32, height as u32, ColorType::Rgba8)
+        .map_err(|err| match err {
+            image_webp::EncodingError::IoError(err) => Error::Io(err),
+            other => Error::WebP(other.to_string()),
+        })?;
+    // RIFF header, followed by the only chunk
+    match webp_file.get(12..) {
+        Some(chunk) if chunk.starts_with(b"VP8L") => Ok(chunk.to_vec()),
+        _ => Err(Error::WebP("the encoder didn't make a VP8L chunk".into())),
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 != 0 {
+        out.push(0);
+    }
+}
+
+fn u24(val: usize) -> [u8; 3] {
+    let [a, b, c, _] = (val as u32).to_le_bytes();
+    [a, b, c]
+}
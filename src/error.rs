@@ -19,9 +19,18 @@ quick_error! {
         Gif(err: gif::EncodingError) {
             display("GIF encoding error: {}", err)
         }
+        GifDecode(err: gif::DecodingError) {
+            display("GIF decoding error: {}", err)
+        }
+        WebP(msg: String) {
+            display("WebP encoding error: {}", msg)
+        }
         NoFrames {
             display("Found no usable frames to encode")
         }
+        TooLarge {
+            display("Unable to make the animation fit in the maximum file size")
+        }
         Io(err: io::Error) {
             from()
             from(_oom: std::collections::TryReserveError) -> (io::ErrorKind::OutOfMemory.into())
@@ -36,6 +45,10 @@ quick_error! {
             from(_e: WrongSizeError) -> ("wrong size".to_string())
             from(e: resize::Error) -> (e.to_string())
         }
+        /// A setting is out of its range
+        InvalidSettings(msg: String) {
+            display("Invalid settings: {}", msg)
+        }
         Quant(liq: imagequant::liq_error) {
             from()
             display("pngquant error: {}", liq)
@@ -44,6 +57,12 @@ quick_error! {
             from()
             display("gif dispose error: {}", gif)
         }
+        Timing(msg: String) {
+            display("{}", msg)
+        }
+        TooManyColors(ordinal_frame_number: usize) {
+            display("Frame {} has too many colors to be encoded losslessly", ordinal_frame_number)
+        }
     }
 }
 
@@ -63,6 +82,16 @@ impl From<gif::EncodingError> for Error {
     }
 }
 
+impl From<gif::DecodingError> for Error {
+    #[cold]
+    fn from(err: gif::DecodingError) -> Self {
+        match err {
+            gif::DecodingError::Io(err) => err.into(),
+            other => Self::GifDecode(other),
+        }
+    }
+}
+
 impl<T> From<ordered_channel::SendError<T>> for Error {
     #[cold]
     fn from(_: ordered_channel::SendError<T>) -> Self {
@@ -9,6 +9,7 @@ pub struct GiflossyImage<'data> {
 
 use rgb::RGB8;
 
+use crate::encoderust::interlaced_line;
 use crate::Error;
 pub type LzwCode = u16;
 
@@ -303,6 +304,7 @@ impl<'a> GiflossyImage<'a> {
         img: &'a [u8],
         width: u16,
         height: u16,
+        interlace: bool,
         transparent: Option<u8>,
         pal: Option<&'a [RGB8]>,
     ) -> Self {
@@ -311,7 +313,7 @@ impl<'a> GiflossyImage<'a> {
             img,
             width,
             height,
-            interlace: false,
+            interlace,
             transparent,
             pal,
         }
@@ -328,15 +330,3 @@ impl<'a> GiflossyImage<'a> {
         }
     }
 }
-
-fn interlaced_line(line: usize, height: usize) -> usize {
-    if line > height / 2 {
-        line * 2 - (height | 1)
-    } else if line > height / 4 {
-        line * 4 - (height & !1 | 2)
-    } else if line > height / 8 {
-        line * 8 - (height & !3 | 4)
-    } else {
-        line * 8
-    }
-}
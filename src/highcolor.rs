@@ -0,0 +1,80 @@
+//! More than 256 colors in a frame, by drawing it as several images, each with its own palette
+
+use crate::error::CatResult;
+use crate::{transparent_index_from_palette, Error, GIFFrame, Writer};
+use gif::DisposalMethod;
+use imgref::{ImgRef, ImgVec};
+use rgb::RGBA8;
+
+/// Every tile has a palette of up to 768 bytes, so there can't be too many of them
+const MAX_TILES: usize = 16;
+/// Tiles smaller than this don't have enough colors to benefit from their own palette
+const MIN_TILE_SIZE: usize = 64;
+
+/// Area of the frame remapped to its own palette
+pub(crate) struct Tile {
+    pub left: u16,
+    pub top: u16,
+    pub image8: ImgVec<u8>,
+    pub pal: Vec<RGBA8>,
+}
+
+impl Writer {
+    /// Quantizes and remaps every tile separately. The tiles cover the whole frame, and are drawn over nothing, so they have no background.
+    pub(crate) fn quantize_tiles(&self, image: ImgRef<RGBA8>, importance_map: &[u8]) -> CatResult<Vec<Tile>> {
+        let (width, height) = (image.width(), image.height());
+        let tile_size = tile_size(width, height);
+
+        let mut tiles = Vec::new();
+        for top in (0..height).step_by(tile_size) {
+            for left in (0..width).step_by(tile_size) {
+                let tile_width = tile_size.min(width - left);
+                let tile_height = tile_size.min(height - top);
+                let tile = image.sub_image(left, top, tile_width, tile_height);
+                let tile_importance: Vec<u8> = importance_map.chunks_exact(width).skip(top).take(tile_height)
+                    .flat_map(|row| &row[left..left + tile_width]).copied().collect();
+                let needs_transparency = tile.pixels().any(|px| px.a < 128);
+
+                let tile = ImgVec::new(tile.to_contiguous_buf().0.into_owned(), tile_width, tile_height);
+                let (liq, remap, liq_image, out_buf) = self.quantize(tile, &tile_importance, true, needs_transparency, false, None)?;
+                let (image8, pal) = self.remap(liq, remap, liq_image, None, out_buf)?;
+                tiles.push(Tile {
+                    left: left.try_into().map_err(|_| Error::WrongSize("too large".into()))?,
+                    top: top.try_into().map_err(|_| Error::WrongSize("too large".into()))?,
+                    image8,
+                    pal,
+                });
+            }
+        }
+        Ok(tiles)
+    }
+}
+
+/// Images drawn before the last tile, and the last tile, which gets the frame's disposal method
+pub(crate) fn tiles_to_frames(tiles: Vec<Tile>, dispose: DisposalMethod) -> CatResult<(Vec<GIFFrame>, GIFFrame)> {
+    let mut frames: Vec<_> = tiles.into_iter().map(|Tile { left, top, mut image8, pal }| {
+        let (pal, transparent_index) = transparent_index_from_palette(pal, image8.as_mut());
+        GIFFrame { left, top, image: image8, pal, transparent_index, dispose: DisposalMethod::Keep }
+    }).collect();
+    let mut last = frames.pop().ok_or(Error::NoFrames)?;
+    last.dispose = dispose;
+    Ok((frames, last))
+}
+
+/// Square tiles, so that there are at most `MAX_TILES` of them
+fn tile_size(width: usize, height: usize) -> usize {
+    let mut size = MIN_TILE_SIZE;
+    while width.div_ceil(size) * height.div_ceil(size) > MAX_TILES {
+        size += MIN_TILE_SIZE / 4;
+    }
+    size
+}
+
+#[test]
+fn tile_count() {
+    assert_eq!(64, tile_size(100, 100));
+    for (width, height) in [(800, 600), (1920, 1080), (10, 4000)] {
+        let size = tile_size(width, height);
+        assert!(width.div_ceil(size) * height.div_ceil(size) <= MAX_TILES);
+    }
+}
@@ -33,9 +33,10 @@
 #![allow(clippy::redundant_closure_for_method_calls)]
 #![allow(clippy::wildcard_imports)]
 
+use encoderapng::ApngEncoder;
 use encoderust::RustEncoder;
 use gif::DisposalMethod;
-use imagequant::{Attributes, Image, QuantizationResult};
+use imagequant::{Attributes, Histogram, Image, QuantizationResult};
 use imgref::*;
 use rgb::*;
 
@@ -47,26 +48,51 @@ use ordered_channel::Sender as OrdQueue;
 pub mod progress;
 use crate::progress::*;
 pub mod c_api;
+mod canvas;
+mod crop;
 mod denoise;
+mod dither;
 use crate::denoise::*;
 pub mod collector;
+mod encoderapng;
 mod encoderust;
+mod encoderwebp;
+use encoderwebp::AnimatedWebP;
+mod highcolor;
 #[doc(inline)]
 pub use crate::collector::Collector;
-use crate::collector::{FrameSource, InputFrame, InputFrameResized};
+use crate::collector::{FrameTime, InputFrame, InputFrameResized};
 
 #[cfg(feature = "gifsicle")]
 mod gifsicle;
 
 mod minipool;
+mod optimize;
+pub use crate::optimize::optimize_gif;
+mod palette_reuse;
+mod resample;
+mod subframes;
+mod target_size;
+mod timing;
+mod truecolor;
+use crate::palette_reuse::{has_close_colors, ColorBins};
+use crate::timing::{DelayRounding, IdleLimit};
+
+pub mod stream;
+#[doc(inline)]
+pub use crate::stream::StreamingEncoder;
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
 
 use crossbeam_channel::{Receiver, Sender};
 use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::io::prelude::*;
 use std::num::NonZeroU8;
 use std::rc::Rc;
 use std::sync::atomic::Ordering::Relaxed;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 #[cfg(feature = "wasm")]
@@ -78,64 +104,415 @@ pub use wasm::GifskiWasm;
 /// Number of repetitions
 pub type Repeat = gif::Repeat;
 
+/// File format written by the [`Writer`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// Animated GIF
+    #[default]
+    Gif,
+    /// Animated PNG. Uses palette frames if [`Settings::global_palette`] is set.
+    /// Otherwise frames aren't quantized, like in WebP, and keep semi-transparent pixels.
+    ///
+    /// The file is written only after all frames have been encoded, because its header needs the number of frames.
+    Apng,
+    /// Animated lossless WebP. Frames aren't quantized, so it's not limited to 256 colors, and keeps semi-transparent pixels.
+    ///
+    /// The file is written only after all frames have been encoded, because its header needs the file size.
+    /// Max size is 16384×16384.
+    WebP,
+}
+
+/// Formats that are written from quantized frames
+#[derive(Copy, Clone)]
+enum QuantizedFormat {
+    Gif,
+    Apng,
+}
+
+/// How presentation timestamps are converted to frame delays, which in GIF are in whole centiseconds.
+///
+/// The decisions are reported via [`ProgressReporter::timing`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TimingMode {
+    /// Delays are rounded, and the rounding error is made up for in the following frames.
+    /// Frames too short to be displayed are lengthened, which may slow down high frame rate animations.
+    #[default]
+    Compensate,
+    /// Fails with [`Error::Timing`] if any frame's timing can't be represented exactly.
+    Strict,
+    /// Frames are dropped to get at most 50fps before they're quantized, so no time is wasted on them.
+    /// Delays are multiples of 2 centiseconds.
+    Max50Fps,
+}
+
+/// How frames are resized. The same policy is applied to every frame.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Resize {
+    /// Downscale large frames to about 800×600, which is a reasonable size for a GIF
+    #[default]
+    Auto,
+    /// Keep the original size
+    None,
+    /// Shrink to fit in this box, keeping the aspect ratio. Missing dimension is unconstrained. Never upscales.
+    Fit { width: Option<u32>, height: Option<u32> },
+    /// Resize to exactly this size, keeping the aspect ratio by cropping edges of the frames
+    Fill { width: u32, height: u32 },
+    /// Resize to exactly this size, stretching the frames if their aspect ratio is different
+    Exact { width: u32, height: u32 },
+    /// Like `Exact`, but never upscales. A dimension larger than the frames is left as it is.
+    ///
+    /// This is what setting both width and height did before there was `Resize`.
+    Stretch { width: u32, height: u32 },
+    /// Multiply width and height by this factor
+    Scale(f32),
+    /// Shrink to have no more than this many pixels, keeping the aspect ratio
+    MaxPixels(u32),
+}
+
+impl Resize {
+    /// What the older width and height settings meant: fit in the box if only one of them is set, `Stretch` to both,
+    /// and `Auto` without either
+    #[must_use]
+    pub fn from_width_height(width: Option<u32>, height: Option<u32>) -> Self {
+        match (width, height) {
+            (None, None) => Self::Auto,
+            (Some(width), Some(height)) => Self::Stretch { width, height },
+            (width, height) => Self::Fit { width, height },
+        }
+    }
+
+    /// The area of the frame to keep (left, top, width, height), and the size it's going to be resized to
+    #[must_use]
+    pub(crate) fn plan(self, (img_w, img_h): (usize, usize)) -> ((usize, usize, usize, usize), (usize, usize)) {
+        let whole_image = (0, 0, img_w, img_h);
+        // u64, because multiplied dimensions could overflow 32-bit usize
+        let (img_w64, img_h64) = (img_w as u64, img_h as u64);
+        let size = match self {
+            Self::Auto => {
+                let factor = ((img_w * img_h + 800 * 600 / 2) as f64 / f64::from(800 * 600)).sqrt().round() as usize;
+                if factor > 1 {
+                    (img_w / factor, img_h / factor)
+                } else {
+                    (img_w, img_h)
+                }
+            },
+            Self::None => (img_w, img_h),
+            Self::Fit { width, height } => {
+                let w = width.map_or(img_w64, |w| u64::from(w).min(img_w64));
+                let h = height.map_or(img_h64, |h| u64::from(h).min(img_h64));
+                let (w, h) = if w * img_h64 <= h * img_w64 {
+                    (w, (img_h64 * w / img_w64).max(1))
+                } else {
+                    ((img_w64 * h / img_h64).max(1), h)
+                };
+                // not larger than the image
+                (w as usize, h as usize)
+            },
+            Self::Fill { width, height } => {
+                let (w, h) = (u64::from(width), u64::from(height));
+                // crop to the aspect ratio of the box, keeping the center
+                let (crop_w, crop_h) = if img_w64 * h > img_h64 * w {
+                    ((img_h64 * w / h).max(1), img_h64)
+                } else {
+                    (img_w64, (img_w64 * h / w).max(1))
+                };
+                let (crop_w, crop_h) = (crop_w as usize, crop_h as usize);
+                return (((img_w - crop_w) / 2, (img_h - crop_h) / 2, crop_w, crop_h), (width as usize, height as usize));
+            },
+            Self::Exact { width, height } => (width as usize, height as usize),
+            Self::Stretch { width, height } => ((width as usize).min(img_w), (height as usize).min(img_h)),
+            Self::Scale(factor) => {
+                let factor = f64::from(factor);
+                (((img_w as f64 * factor).round() as usize).max(1), ((img_h as f64 * factor).round() as usize).max(1))
+            },
+            Self::MaxPixels(max) => {
+                if img_w64 * img_h64 <= u64::from(max) {
+                    (img_w, img_h)
+                } else {
+                    let factor = (f64::from(max) / (img_w as f64 * img_h as f64)).sqrt();
+                    (((img_w as f64 * factor) as usize).max(1), ((img_h as f64 * factor) as usize).max(1))
+                }
+            },
+        };
+        (whole_image, size)
+    }
+
+    #[cold]
+    fn validate(self) -> GifResult<()> {
+        let valid = |size: u32| size > 0 && size <= 1 << 16;
+        let ok = match self {
+            Self::Auto | Self::None => true,
+            Self::Fit { width, height } => width.into_iter().chain(height).all(valid),
+            Self::Fill { width, height } | Self::Exact { width, height } | Self::Stretch { width, height } => valid(width) && valid(height),
+            Self::Scale(factor) => factor > 0. && factor <= 16.,
+            Self::MaxPixels(max) => max > 0,
+        };
+        if !ok {
+            return Err(Error::InvalidSettings(format!("invalid resize: {self:?}")));
+        }
+        Ok(())
+    }
+}
+
+/// Area of input frames to keep. Cropping is done before resizing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Crop {
+    /// Keep whole frames
+    #[default]
+    None,
+    /// In pixels of the input frames. It's clipped to the frame size.
+    Rect { left: u32, top: u32, width: u32, height: u32 },
+    /// Remove borders of constant color, such as letterboxing, found in the first few frames
+    Auto,
+}
+
+/// What to do with frames that have a different size than the first frame.
+///
+/// Sizes are compared after [`Settings::crop`], and frames are fitted before they're resized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum FrameFit {
+    /// Keep the size, and center the frame. Smaller frames are padded with transparency, larger ones are cropped.
+    #[default]
+    Center,
+    /// Resize to fit, keeping the aspect ratio, and center the frame
+    Scale,
+    /// Keep the size, and align the frame to the top left corner
+    TopLeft,
+}
+
+/// Filter used for resizing frames
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Resample {
+    /// Nearest neighbor. Keeps hard edges of pixel art, but drops pixels when downscaling.
+    Point,
+    /// Bilinear. Soft, without any ringing.
+    Triangle,
+    /// Sharp, with little ringing. Good for text and UI graphics.
+    CatmullRom,
+    /// A bit softer than Catmull-Rom, and without ringing.
+    Mitchell,
+    /// The sharpest, but may add halos around high-contrast edges
+    #[default]
+    Lanczos3,
+}
+
+/// Keeping exact colors of inputs that already have few colors, such as pixel art or screenshots of UI
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Lossless {
+    /// Colors are quantized, dithered and denoised as usual
+    #[default]
+    Off,
+    /// Frames with few enough colors are kept pixel-exact, and the others are quantized
+    IfPossible,
+    /// Fails with [`Error::TooManyColors`] if any frame has too many colors
+    Required,
+}
+
 /// Encoding settings for the `new()` function
+///
+/// Start from [`Settings::default()`] and change the fields you need, or use [`Settings::builder()`].
 #[derive(Copy, Clone)]
+#[non_exhaustive]
 pub struct Settings {
-    /// Resize to max this width if non-0.
-    pub width: Option<u32>,
-    /// Resize to max this height if width is non-0. Note that aspect ratio is not preserved.
-    pub height: Option<u32>,
+    /// Area of the input frames to keep. It's applied before `resize`.
+    pub crop: Crop,
+    /// By default, large frames are downscaled to about 800×600. See [`Resize`].
+    pub resize: Resize,
+    /// Frames that end up with a different size than the first frame are made to fit this way
+    pub frame_fit: FrameFit,
+    /// Filter for resizing. Lanczos3 by default.
+    pub resample: Resample,
+    /// Average colors in linear light when resizing. Slower, but doesn't darken thin bright details.
+    pub linear_light: bool,
+    /// 0-4. Sharpening applied after resizing, when the frames are resized. 0 disables it.
+    pub unsharp: f32,
     /// 1-100, but useful range is 50-100. Recommended to set to 100.
     pub quality: u8,
     /// Lower quality, but faster encode.
     pub fast: bool,
     /// Sets the looping method for the image sequence.
     pub repeat: Repeat,
-}
-
-#[derive(Copy, Clone)]
-#[non_exhaustive]
-struct SettingsExt {
-    pub s: Settings,
-    pub max_threads: NonZeroU8,
+    /// If set, quality and dimensions will be lowered as needed to make the file fit in this many bytes.
+    ///
+    /// This keeps all frames in memory, and may need to encode the animation several times.
+    pub max_size: Option<u64>,
+    /// Use one palette for the whole animation, instead of a new palette for every frame.
+    ///
+    /// The palette is made from a sample of the first frames, so it works best for animations with consistent colors,
+    /// such as screen recordings. It avoids color flickering between frames, and saves space for each frame's palette.
+    pub global_palette: bool,
+    /// 1-100. Quality of temporal denoising. Lower values reduce motion. `None` uses `quality`.
+    pub motion_quality: Option<u8>,
+    /// 1-100. Quality of lossy LZW compression. Lower values add noise and streaks. `None` uses `quality`.
+    ///
+    /// Has no effect if the `gifsicle` feature hasn't been enabled, or if the output format isn't GIF.
+    pub lossy_quality: Option<u8>,
+    /// Significantly slower encoding, but may look a bit better.
     pub extra_effort: bool,
-    pub motion_quality: u8,
-    pub giflossy_quality: u8,
+    /// 0-100. Makes dithering stable between frames, at a cost of accuracy. 0 dithers every frame independently.
+    ///
+    /// Higher values use a stronger fixed dither pattern, and keep more pixels from the previous frame if they have changed only a little.
+    /// This reduces "crawling" of dithering in still areas, and makes files smaller. Has no effect in `lossless` mode.
+    pub dither_stability: u8,
+    /// Draw keyframes (the first frame, and frames after scene cuts) as tiles, each with its own palette of 256 colors.
+    ///
+    /// Reduces banding in still-ish images, but makes files larger. Only for GIF, without `global_palette` or `lossless`.
+    pub high_color: bool,
+    /// Store rows of GIF frames interlaced, so that large images appear progressively while they're loading. Makes files a bit larger.
+    pub interlaced: bool,
+    /// Write far-apart changes of a frame as separate GIF images, so that the unchanged area between them isn't stored again.
+    ///
+    /// Makes screen recordings smaller, but the extra images have no delay. Browsers show every image for at least a few milliseconds,
+    /// and some stall on them, so animations with many frames play slower. Only for GIF.
+    pub split_frames: bool,
+    /// 0-255. A frame is merged into the next one, and their durations are added together, if no pixel differs by more than this.
+    ///
+    /// The differences add up over a run of merged frames: frames are merged only as long as the sum of their maximum differences
+    /// is at most this threshold, so a slow fade isn't merged into one frame.
+    /// Helps with noise in video sources that makes every frame unique. 0 merges only identical frames.
+    pub merge_threshold: u8,
+    /// Background color for semitransparent pixels. If not set, their transparency is dithered.
     pub matte: Option<RGB8>,
+    /// GIF by default.
+    pub format: OutputFormat,
+    /// What to do when frame timestamps don't fit GIF's delays
+    pub timing: TimingMode,
+    /// Seconds. Stretches of time in which nothing much changes (apart from something small, like a blinking cursor) are cut down to this length.
+    ///
+    /// Useful for screencasts with long pauses. `None` keeps the original timing.
+    pub idle_limit: Option<f64>,
+    /// Pixel-exact output for inputs that have at most 256 colors per frame (or in total with `global_palette`).
+    ///
+    /// A frame that has transparent pixels can have only 255 colors. With `global_palette` the palette is made from
+    /// the first few frames, so a later frame that has other colors is quantized, or fails with [`Lossless::Required`].
+    ///
+    /// Disables dithering, denoising and lossy compression, and resizes with nearest-neighbor. Semitransparent pixels still lose their transparency.
+    pub lossless: Lossless,
 }
 
 impl Settings {
+    /// Settings with validation of their ranges
+    ///
+    /// ```rust
+    /// let settings = gifski::Settings::builder().quality(90).lossy_quality(70).build()?;
+    /// # Ok::<_, gifski::Error>(())
+    /// ```
+    #[inline]
+    pub fn builder() -> SettingsBuilder {
+        SettingsBuilder { s: Self::default() }
+    }
+
+    #[cold]
+    fn validate(&self) -> GifResult<()> {
+        if self.quality == 0 || self.quality > 100 {
+            return Err(Error::InvalidSettings("quality must be 1-100".into()));
+        }
+        if self.motion_quality.is_some_and(|q| q == 0 || q > 100) {
+            return Err(Error::InvalidSettings("motion quality must be 1-100".into()));
+        }
+        if self.lossy_quality.is_some_and(|q| q == 0 || q > 100) {
+            return Err(Error::InvalidSettings("lossy quality must be 1-100".into()));
+        }
+        if self.dither_stability > 100 {
+            return Err(Error::InvalidSettings("dither stability must be 0-100".into()));
+        }
+        self.crop.validate()?;
+        self.resize.validate()?;
+        if !(self.unsharp >= 0. && self.unsharp <= 4.) {
+            return Err(Error::InvalidSettings("unsharp must be 0-4".into()));
+        }
+        if self.idle_limit.is_some_and(|s| !(s > 0. && s.is_finite())) {
+            return Err(Error::InvalidSettings("idle limit must be a positive number of seconds".into()));
+        }
+        if self.max_size == Some(0) {
+            return Err(Error::InvalidSettings("max file size must be larger than 0".into()));
+        }
+        Ok(())
+    }
+
     /// quality is used in other places, like gifsicle or frame differences,
     /// and it's better to lower quality there before ruining quantization
     pub(crate) fn color_quality(&self) -> u8 {
         (u16::from(self.quality) * 4 / 3).min(100) as u8
     }
 
-    /// `add_frame` is going to resize the images to this size.
-    #[must_use]
-    #[inline]
-    pub fn dimensions_for_image(&self, width: usize, height: usize) -> (usize, usize) {
-        dimensions_for_image((width, height), (self.width, self.height))
+    pub(crate) fn motion_quality(&self) -> u8 {
+        self.motion_quality.unwrap_or(self.quality)
+    }
+
+    pub(crate) fn lossy_quality(&self) -> u8 {
+        self.lossy_quality.unwrap_or(self.quality)
+    }
+
+    /// WebP, and APNG without the global palette, get frames straight from the denoiser
+    fn is_quantized(&self) -> bool {
+        match self.format {
+            OutputFormat::Gif => true,
+            OutputFormat::Apng => self.global_palette,
+            OutputFormat::WebP => false,
+        }
+    }
+
+    /// Lossy LZW is only for GIF
+    fn uses_gifsicle(&self) -> bool {
+        cfg!(feature = "gifsicle") && self.format == OutputFormat::Gif
     }
-}
 
-impl SettingsExt {
     pub(crate) fn gifsicle_loss(&self) -> u32 {
-        if cfg!(feature = "gifsicle") && self.giflossy_quality < 100 {
-            ((100. / 5. - f32::from(self.giflossy_quality) / 5.).powf(1.8).ceil() as u32 + 10) * 10
+        if self.uses_gifsicle() && self.lossless == Lossless::Off {
+            gifsicle_loss_for_quality(self.lossy_quality())
         } else {
             0
         }
     }
 
     pub(crate) fn dithering_level(&self) -> f32 {
-        let gifsicle_quality = if cfg!(feature = "gifsicle") { self.giflossy_quality } else { 100 };
+        let gifsicle_quality = if self.uses_gifsicle() { self.lossy_quality() } else { 100 };
         debug_assert!(gifsicle_quality <= 100);
         // lossy LZW adds its own dithering, so the input could be less nosiy to compensate
         // but don't change dithering unless gifsicle quality < 90, and don't completely disable it
         let gifsicle_factor = 0.25 + f32::from(gifsicle_quality) * (1. / 100. * 1. / 0.9 * 0.75);
 
-        (f32::from(self.s.quality) * (1. / 50. * gifsicle_factor) - 1.).clamp(0.2, 1.)
+        (f32::from(self.quality) * (1. / 50. * gifsicle_factor) - 1.).clamp(0.2, 1.)
+    }
+
+    /// The fixed pattern gets stronger with `dither_stability`, from half of the usual dithering at 1 to all of it at 100
+    pub(crate) fn ordered_dithering_level(&self) -> f32 {
+        self.dithering_level() * (0.5 + f32::from(self.dither_stability.min(100)) / 200.)
+    }
+
+    /// `add_frame` is going to resize the images to this size.
+    ///
+    /// The image is the one already cropped with [`Settings::crop`].
+    #[must_use]
+    #[inline]
+    pub fn dimensions_for_image(&self, width: usize, height: usize) -> (usize, usize) {
+        self.resize.plan((width, height)).1
+    }
+
+    /// Area (left, top, width, height) of the input image that is kept before resizing. It's smaller than the image only with `Resize::Fill`.
+    ///
+    /// The image is the one already cropped with [`Settings::crop`].
+    #[must_use]
+    #[inline]
+    pub fn crop_for_image(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        self.resize.plan((width, height)).0
+    }
+}
+
+/// 0 for quality 100 and above, and without the `gifsicle` feature
+pub(crate) fn gifsicle_loss_for_quality(lossy_quality: u8) -> u32 {
+    if cfg!(feature = "gifsicle") && lossy_quality < 100 {
+        ((100. / 5. - f32::from(lossy_quality) / 5.).powf(1.8).ceil() as u32 + 10) * 10
+    } else {
+        0
     }
 }
 
@@ -143,19 +520,224 @@ impl Default for Settings {
     #[inline]
     fn default() -> Self {
         Self {
-            width: None, height: None,
+            crop: Crop::None,
+            resize: Resize::Auto,
+            frame_fit: FrameFit::Center,
+            resample: Resample::Lanczos3,
+            linear_light: false,
+            unsharp: 0.,
             quality: 100,
             fast: false,
             repeat: Repeat::Infinite,
+            max_size: None,
+            global_palette: false,
+            motion_quality: None,
+            lossy_quality: None,
+            extra_effort: false,
+            dither_stability: 0,
+            high_color: false,
+            interlaced: false,
+            split_frames: false,
+            merge_threshold: 0,
+            matte: None,
+            format: OutputFormat::Gif,
+            timing: TimingMode::Compensate,
+            idle_limit: None,
+            lossless: Lossless::Off,
         }
     }
 }
 
+/// See [`Settings::builder()`]. The fields are documented in [`Settings`].
+#[derive(Copy, Clone)]
+#[must_use]
+pub struct SettingsBuilder {
+    s: Settings,
+}
+
+impl SettingsBuilder {
+    /// Cropping before resizing. See [`Crop`]
+    #[inline]
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.s.crop = crop;
+        self
+    }
+
+    /// See [`Resize`]
+    #[inline]
+    pub fn resize(mut self, resize: Resize) -> Self {
+        self.s.resize = resize;
+        self
+    }
+
+    /// Frames of different sizes. See [`FrameFit`]
+    #[inline]
+    pub fn frame_fit(mut self, frame_fit: FrameFit) -> Self {
+        self.s.frame_fit = frame_fit;
+        self
+    }
+
+    /// Filter for resizing. See [`Resample`]
+    #[inline]
+    pub fn resample(mut self, resample: Resample) -> Self {
+        self.s.resample = resample;
+        self
+    }
+
+    /// Resize in linear light. See [`Settings::linear_light`]
+    #[inline]
+    pub fn linear_light(mut self, enabled: bool) -> Self {
+        self.s.linear_light = enabled;
+        self
+    }
+
+    /// 0-4. Sharpening after resizing
+    #[inline]
+    pub fn unsharp(mut self, amount: f32) -> Self {
+        self.s.unsharp = amount;
+        self
+    }
+
+    /// 1-100
+    #[inline]
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.s.quality = quality;
+        self
+    }
+
+    /// Lower quality, but faster encode
+    #[inline]
+    pub fn fast(mut self, fast: bool) -> Self {
+        self.s.fast = fast;
+        self
+    }
+
+    /// Looping of the animation
+    #[inline]
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.s.repeat = repeat;
+        self
+    }
+
+    /// Max file size in bytes. See [`Settings::max_size`]
+    #[inline]
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.s.max_size = Some(bytes);
+        self
+    }
+
+    /// One palette for all frames. See [`Settings::global_palette`]
+    #[inline]
+    pub fn global_palette(mut self, enabled: bool) -> Self {
+        self.s.global_palette = enabled;
+        self
+    }
+
+    /// 1-100. Lower values reduce motion
+    #[inline]
+    pub fn motion_quality(mut self, quality: u8) -> Self {
+        self.s.motion_quality = Some(quality);
+        self
+    }
+
+    /// 1-100. Lower values add noise
+    #[inline]
+    pub fn lossy_quality(mut self, quality: u8) -> Self {
+        self.s.lossy_quality = Some(quality);
+        self
+    }
+
+    /// Slower, but may look a bit better
+    #[inline]
+    pub fn extra_effort(mut self, enabled: bool) -> Self {
+        self.s.extra_effort = enabled;
+        self
+    }
+
+    /// Shorten still parts of the animation to at most this many seconds. See [`Settings::idle_limit`]
+    #[inline]
+    pub fn idle_limit(mut self, seconds: f64) -> Self {
+        self.s.idle_limit = Some(seconds);
+        self
+    }
+
+    /// More than 256 colors in keyframes. See [`Settings::high_color`]
+    #[inline]
+    pub fn high_color(mut self, enabled: bool) -> Self {
+        self.s.high_color = enabled;
+        self
+    }
+
+    /// Interlaced GIF frames. See [`Settings::interlaced`]
+    #[inline]
+    pub fn interlaced(mut self, enabled: bool) -> Self {
+        self.s.interlaced = enabled;
+        self
+    }
+
+    /// Separate images for far-apart changes. See [`Settings::split_frames`]
+    #[inline]
+    pub fn split_frames(mut self, enabled: bool) -> Self {
+        self.s.split_frames = enabled;
+        self
+    }
+
+    /// 0-100. Trade accuracy of dithering for stability between frames. See [`Settings::dither_stability`]
+    #[inline]
+    pub fn dither_stability(mut self, stability: u8) -> Self {
+        self.s.dither_stability = stability;
+        self
+    }
+
+    /// Merge frames that differ by at most this much. See [`Settings::merge_threshold`]
+    #[inline]
+    pub fn merge_threshold(mut self, threshold: u8) -> Self {
+        self.s.merge_threshold = threshold;
+        self
+    }
+
+    /// Background color for semitransparent pixels
+    #[inline]
+    pub fn matte(mut self, color: RGB8) -> Self {
+        self.s.matte = Some(color);
+        self
+    }
+
+    /// GIF, APNG or WebP
+    #[inline]
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.s.format = format;
+        self
+    }
+
+    /// Rounding of frame delays. See [`TimingMode`]
+    #[inline]
+    pub fn timing(mut self, timing: TimingMode) -> Self {
+        self.s.timing = timing;
+        self
+    }
+
+    /// Exact colors for inputs with few colors. See [`Lossless`]
+    #[inline]
+    pub fn lossless(mut self, lossless: Lossless) -> Self {
+        self.s.lossless = lossless;
+        self
+    }
+
+    /// Fails if any of the values is out of range
+    #[inline]
+    pub fn build(self) -> GifResult<Settings> {
+        self.s.validate()?;
+        Ok(self.s)
+    }
+}
+
 /// Perform GIF writing
 pub struct Writer {
     /// Input frame decoder results
     queue_iter: Option<Receiver<InputFrame>>,
-    settings: SettingsExt,
+    settings: Settings,
+    max_threads: NonZeroU8,
     /// Colors the caller has specified as fixed (i.e. key colours)
     /// This can't be in settings because that would cause it to lose Copy.
     /// Additionally to avoid breaking C API compatibility this has to be mutable there too.
@@ -171,6 +753,20 @@ struct GIFFrame {
     transparent_index: Option<u8>,
 }
 
+/// Output file format backend. Frames are compressed in parallel, and then written in order.
+pub(crate) trait FrameEncoder<W: Write> {
+    type Frame: Send;
+
+    fn new(writer: W, written: Rc<Cell<u64>>) -> Self;
+    fn compress_frame(f: GIFFrame, settings: &Settings) -> CatResult<Self::Frame>;
+    /// For formats that need the first frame to cover the whole screen
+    fn compress_first_frame(f: GIFFrame, _screen_width: u16, _screen_height: u16, settings: &Settings) -> CatResult<Self::Frame> {
+        Self::compress_frame(f, settings)
+    }
+    fn write_frame(&mut self, frame: Self::Frame, delay: u16, screen_width: u16, screen_height: u16, settings: &Settings) -> CatResult<()>;
+    fn finish(&mut self, settings: &Settings) -> CatResult<()>;
+}
+
 /// Frame before quantization
 struct DiffMessage {
     /// 1..
@@ -178,6 +774,8 @@ struct DiffMessage {
     pts: f64, frame_duration: f64,
     image: ImgVec<RGBA8>,
     importance_map: Vec<u8>,
+    /// First frame of a new scene, unrelated to the previous frame
+    scene_cut: bool,
 }
 
 struct QuantizeMessage {
@@ -192,6 +790,13 @@ struct QuantizeMessage {
     dispose: gif::DisposalMethod,
     end_pts: f64,
     has_next_frame: bool,
+    /// Quantized like the first frame
+    keyframe: bool,
+    /// Colors are similar to the frame that had its own palette last, so it's remapped to the previous frame's palette
+    reuse_palette: bool,
+    /// Quantized in tiles
+    high_color: bool,
+    global_pal: Option<Arc<[RGBA8]>>,
 }
 
 /// Frame post quantization, before remap
@@ -200,11 +805,55 @@ struct RemapMessage {
     ordinal_frame_number: usize,
     end_pts: f64,
     dispose: DisposalMethod,
+    quantized: Quantized,
+    has_next_frame: bool,
+    /// If set, the remapped frame has to use exactly this palette
+    global_pal: Option<Arc<[RGBA8]>>,
+}
+
+/// Palette from liq, and the image to remap with it once the background is known
+struct LiqQuantized {
     liq: Attributes,
     remap: QuantizationResult,
     liq_image: Image<'static>,
     out_buf: Vec<u8>,
-    has_next_frame: bool,
+}
+
+enum Quantized {
+    /// Boxed, because it's much larger than the other variants
+    Liq(Box<LiqQuantized>),
+    /// `Lossless` mode, with every color of the image in the palette
+    Exact {
+        image: ImgVec<RGBA8>,
+        pal: Vec<RGBA8>,
+    },
+    /// `dither_stability` mode, with palette from liq, and own remapping
+    Ordered {
+        image: ImgVec<RGBA8>,
+        pal: Vec<RGBA8>,
+    },
+    /// To be remapped to the previous frame's palette, which isn't known until then.
+    /// The palette from liq is used if the previous one turns out to lack some colors.
+    ReusePalette {
+        image: ImgVec<RGBA8>,
+        fallback: Box<LiqQuantized>,
+    },
+    /// `high_color` keyframe, already remapped
+    Tiled {
+        tiles: Vec<highcolor::Tile>,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl Quantized {
+    fn size(&self) -> (usize, usize) {
+        match self {
+            Self::Liq(q) => (q.liq_image.width(), q.liq_image.height()),
+            Self::Exact { image, .. } | Self::Ordered { image, .. } | Self::ReusePalette { image, .. } => (image.width(), image.height()),
+            Self::Tiled { width, height, .. } => (*width, *height),
+        }
+    }
 }
 
 /// Frame post quantization and remap
@@ -214,11 +863,16 @@ struct FrameMessage {
     /// 1..
     ordinal_frame_number: usize,
     end_pts: f64,
+    /// Drawn before `frame`, without delay, when the changes are far apart
+    subframes: Vec<GIFFrame>,
     frame: GIFFrame,
     screen_width: u16,
     screen_height: u16,
 }
 
+/// With `Settings::global_palette`, the palette is made from this many frames
+const GLOBAL_PALETTE_SAMPLE_FRAMES: usize = 20;
+
 /// Start new encoding on two threads.
 ///
 /// Encoding is always multi-threaded, and the `Collector` and `Writer`
@@ -250,12 +904,7 @@ struct FrameMessage {
 /// ```
 #[inline]
 pub fn new(settings: Settings) -> GifResult<(Collector, Writer)> {
-    if settings.quality == 0 || settings.quality > 100 {
-        return Err(Error::WrongSize("quality must be 1-100".into())); // I forgot to add a better error variant
-    }
-    if settings.width.unwrap_or(0) > 1 << 16 || settings.height.unwrap_or(0) > 1 << 16 {
-        return Err(Error::WrongSize("image size too large".into()));
-    }
+    settings.validate()?;
 
     let max_threads = thread::available_parallelism().map(|t| t.get().min(255) as u8).unwrap_or(8);
     let (queue, queue_iter) = crossbeam_channel::bounded(5.min(max_threads.into())); // should be sufficient for denoiser lookahead
@@ -265,14 +914,8 @@ pub fn new(settings: Settings) -> GifResult<(Collector, Writer)> {
         },
         Writer {
             queue_iter: Some(queue_iter),
-            settings: SettingsExt {
-                s: settings,
-                max_threads: max_threads.try_into()?,
-                motion_quality: settings.quality,
-                giflossy_quality: settings.quality,
-                extra_effort: false,
-                matte: None,
-            },
+            settings,
+            max_threads: max_threads.try_into()?,
             fixed_colors: Vec::new(),
         },
     ))
@@ -280,23 +923,29 @@ pub fn new(settings: Settings) -> GifResult<(Collector, Writer)> {
 
 #[inline(never)]
 #[cfg_attr(debug_assertions, track_caller)]
-fn resized_binary_alpha(image: ImgVec<RGBA8>, width: Option<u32>, height: Option<u32>, matte: Option<RGB8>) -> CatResult<ImgVec<RGBA8>> {
-    let (width, height) = dimensions_for_image((image.width(), image.height()), (width, height));
-
-    let mut image = if width != image.width() || height != image.height() {
-        let tmp = image.as_ref();
-        let (buf, img_width, img_height) = tmp.to_contiguous_buf();
-        assert_eq!(buf.len(), img_width * img_height);
-
-        let mut r = resize::new(img_width, img_height, width, height, resize::Pixel::RGBA8P, resize::Type::Lanczos3)?;
-        let mut dst = vec![RGBA8::new(0, 0, 0, 0); width * height];
-        r.resize(&buf, &mut dst)?;
-        ImgVec::new(dst, width, height)
+fn resized_binary_alpha(image: ImgVec<RGBA8>, settings: &Settings) -> CatResult<ImgVec<RGBA8>> {
+    let lossless = settings.lossless != Lossless::Off;
+    // nearest-neighbor doesn't add any new colors
+    let settings = &if lossless {
+        Settings { resample: Resample::Point, linear_light: false, unsharp: 0., ..*settings }
+    } else {
+        *settings
+    };
+    let ((left, top, crop_width, crop_height), (width, height)) = settings.resize.plan((image.width(), image.height()));
+
+    let mut image = if width != image.width() || height != image.height() || crop_width != image.width() || crop_height != image.height() {
+        let tmp = image.sub_image(left, top, crop_width, crop_height);
+        if width == crop_width && height == crop_height {
+            let (buf, img_width, img_height) = tmp.to_contiguous_buf();
+            ImgVec::new(buf.into_owned(), img_width, img_height)
+        } else {
+            resample::resample(tmp, width, height, settings)?
+        }
     } else {
         image
     };
 
-    if let Some(matte) = matte {
+    if let Some(matte) = settings.matte {
         image.pixels_mut().filter(|px| px.a < 255 && px.a > 0).for_each(move |px| {
             let alpha = u16::from(px.a);
             let inv_alpha = 255 - alpha;
@@ -308,7 +957,11 @@ fn resized_binary_alpha(image: ImgVec<RGBA8>, width: Option<u32>, height: Option
                 a: 255,
             };
         });
-    } else {
+    } else if lossless {
+        image.pixels_mut().filter(|px| px.a < 255).for_each(|px| {
+            *px = if px.a < 128 { RGBA8::new(0, 0, 0, 0) } else { px.with_alpha(255) };
+        });
+    } else if settings.is_quantized() {
         dither_image(image.as_mut());
     }
 
@@ -364,33 +1017,6 @@ fn dither_image(mut image: ImgRefMut<RGBA8>) {
     }
 }
 
-/// `add_frame` is going to resize the image to this size.
-/// The `Option` args are user-specified max width and max height
-#[inline(never)]
-fn dimensions_for_image((img_w, img_h): (usize, usize), resize_to: (Option<u32>, Option<u32>)) -> (usize, usize) {
-    match resize_to {
-        (None, None) => {
-            let factor = ((img_w * img_h + 800 * 600 / 2) as f64 / f64::from(800 * 600)).sqrt().round() as usize;
-            if factor > 1 {
-                (img_w / factor, img_h / factor)
-            } else {
-                (img_w, img_h)
-            }
-        },
-        (Some(w), Some(h)) => {
-            ((w as usize).min(img_w), (h as usize).min(img_h))
-        },
-        (Some(w), None) => {
-            let w = (w as usize).min(img_w);
-            (w, img_h * w / img_w)
-        },
-        (None, Some(h)) => {
-            let h = (h as usize).min(img_h);
-            (img_w * h / img_h, h)
-        },
-    }
-}
-
 #[derive(Copy, Clone)]
 enum LastFrameDuration {
     FixedOffset(f64),
@@ -416,22 +1042,22 @@ impl LastFrameDuration {
 
 /// Encode collected frames
 impl Writer {
-    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[deprecated(note = "use Settings fields instead")]
     #[doc(hidden)]
     pub fn set_extra_effort(&mut self, enabled: bool) {
         self.settings.extra_effort = enabled;
     }
 
-    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[deprecated(note = "use Settings fields instead")]
     #[doc(hidden)]
     pub fn set_motion_quality(&mut self, q: u8) {
-        self.settings.motion_quality = q;
+        self.settings.motion_quality = Some(q);
     }
 
-    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[deprecated(note = "use Settings fields instead")]
     #[doc(hidden)]
     pub fn set_lossy_quality(&mut self, q: u8) {
-        self.settings.giflossy_quality = q;
+        self.settings.lossy_quality = Some(q);
     }
 
     /// Adds a fixed color that will be kept in the palette at all times.
@@ -444,7 +1070,7 @@ impl Writer {
         }
     }
 
-    #[deprecated(note = "please don't use, it will be in Settings eventually")]
+    #[deprecated(note = "use Settings fields instead")]
     #[doc(hidden)]
     pub fn set_matte_color(&mut self, col: RGB8) {
         self.settings.matte = Some(col);
@@ -455,22 +1081,25 @@ impl Writer {
     /// Avoids wasting palette on pixels identical to the background.
     ///
     /// `background` is the previous frame.
-    fn quantize(&self, image: ImgVec<RGBA8>, importance_map: &[u8], first_frame: bool, needs_transparency: bool, prev_frame_keeps: bool) -> CatResult<(Attributes, QuantizationResult, Image<'static>, Vec<u8>)> {
+    ///
+    /// With `global_pal` the palette is made only from its fixed colors, so every frame gets the same palette.
+    fn quantize(&self, image: ImgVec<RGBA8>, importance_map: &[u8], first_frame: bool, needs_transparency: bool, prev_frame_keeps: bool, global_pal: Option<&[RGBA8]>) -> CatResult<(Attributes, QuantizationResult, Image<'static>, Vec<u8>)> {
         let mut liq = Attributes::new();
-        if self.settings.s.fast && !first_frame {
+        if self.settings.fast && !first_frame {
             liq.set_speed(10)?;
         } else if self.settings.extra_effort {
             liq.set_speed(1)?;
         }
         let quality = if !first_frame {
-            self.settings.s.color_quality()
+            self.settings.color_quality()
         } else {
             100 // the first frame is too important to ruin it
         };
         liq.set_quality(0, quality)?;
-        if self.settings.s.quality < 50 {
-            let min_colors = 5 + self.fixed_colors.len() as u32;
-            liq.set_max_colors(u32::from(self.settings.s.quality * 2).max(min_colors).next_power_of_two().min(256))?;
+        if let Some(global_pal) = global_pal {
+            liq.set_max_colors(global_pal.len().max(2) as u32)?;
+        } else {
+            self.limit_colors(&mut liq)?;
         }
         let (buf, width, height) = image.into_contiguous_buf();
         let mut img = liq.new_image(buf, width, height, 0.)?;
@@ -481,19 +1110,26 @@ impl Writer {
         if first_frame || prev_frame_keeps {
             img.set_importance_map(importance_map)?;
         }
-        // first frame may be transparent too, so it's not just for diffs
-        if needs_transparency {
-            img.add_fixed_color(RGBA8::new(0, 0, 0, 0))?;
-        }
-        // user may have colors which need to be preserved and left undithered
-        for color in &self.fixed_colors {
-            img.add_fixed_color(RGBA8::new(color.r, color.g, color.b, 255))?;
+        if let Some(global_pal) = global_pal {
+            // it already has the transparent and user's fixed colors
+            for &color in global_pal {
+                img.add_fixed_color(color)?;
+            }
+        } else {
+            // first frame may be transparent too, so it's not just for diffs
+            if needs_transparency {
+                img.add_fixed_color(RGBA8::new(0, 0, 0, 0))?;
+            }
+            // user may have colors which need to be preserved and left undithered
+            for color in &self.fixed_colors {
+                img.add_fixed_color(RGBA8::new(color.r, color.g, color.b, 255))?;
+            }
         }
 
         let mut res = liq.quantize(&mut img)?;
 
         // GIF only stores power-of-two palette sizes
-        if self.settings.extra_effort {
+        if self.settings.extra_effort && global_pal.is_none() {
             let len = res.palette_len();
             // it has little impact on compression (128c -> 64c is only 7% smaller)
             if (len < 128 || len > 220) && len != len.next_power_of_two() {
@@ -511,6 +1147,59 @@ impl Writer {
         Ok((liq, res, img, out))
     }
 
+    /// Palette with all colors of the frames (and the fixed colors), or index of the frame that didn't fit
+    fn exact_palette<'a>(&self, frames: impl Iterator<Item = ImgRef<'a, RGBA8>>, needs_transparency: bool) -> Result<Vec<RGBA8>, usize> {
+        let max_colors = if needs_transparency { 255 } else { 256 };
+        let mut colors: HashSet<RGBA8> = self.fixed_colors.iter().map(|c| c.with_alpha(255)).collect();
+        for (n, frame) in frames.enumerate() {
+            for px in frame.pixels().filter(|px| px.a > 0) {
+                if colors.insert(px) && colors.len() > max_colors {
+                    return Err(n);
+                }
+            }
+        }
+        let mut pal: Vec<_> = colors.into_iter().collect();
+        pal.sort_unstable_by_key(|c| (c.r, c.g, c.b));
+        if needs_transparency {
+            pal.insert(0, RGBA8::new(0, 0, 0, 0));
+        }
+        Ok(pal)
+    }
+
+    fn limit_colors(&self, liq: &mut Attributes) -> CatResult<()> {
+        if self.settings.quality < 50 {
+            let min_colors = 5 + self.fixed_colors.len() as u32;
+            liq.set_max_colors(u32::from(self.settings.quality * 2).max(min_colors).next_power_of_two().min(256))?;
+        }
+        Ok(())
+    }
+
+    /// Makes one palette from a sample of frames, weighed by their importance maps
+    #[inline(never)]
+    fn global_palette(&self, sample: &[DiffMessage]) -> CatResult<Vec<RGBA8>> {
+        let mut liq = Attributes::new();
+        if self.settings.extra_effort {
+            liq.set_speed(1)?;
+        }
+        liq.set_quality(0, 100)?;
+        self.limit_colors(&mut liq)?;
+
+        let mut hist = Histogram::new(&liq);
+        // all frames after the first need transparency for unchanged pixels
+        hist.add_fixed_color(RGBA8::new(0, 0, 0, 0), 0.)?;
+        for color in &self.fixed_colors {
+            hist.add_fixed_color(RGBA8::new(color.r, color.g, color.b, 255), 0.)?;
+        }
+        for frame in sample {
+            let image = frame.image.as_ref();
+            let mut img = Image::new_stride_borrowed(&liq, image.buf(), image.width(), image.height(), image.stride(), 0.)?;
+            img.set_importance_map(&frame.importance_map[..])?;
+            hist.add_image(&liq, &mut img)?;
+        }
+        let mut res = hist.quantize(&liq)?;
+        Ok(res.palette_vec())
+    }
+
     fn remap<'a>(&self, liq: Attributes, mut res: QuantizationResult, mut img: Image<'a>, background: Option<ImgRef<'a, RGBA8>>, mut pal_img: Vec<u8>) -> CatResult<(ImgVec<u8>, Vec<RGBA8>)> {
         if let Some(bg) = background {
             img.set_background(Image::new_stride_borrowed(&liq, bg.buf(), bg.width(), bg.height(), bg.stride(), 0.)?)?;
@@ -522,25 +1211,35 @@ impl Writer {
         Ok((Img::new(pal_img, img.width(), img.height()), pal))
     }
 
+    fn write_frames(&self, format: QuantizedFormat, write_queue: Receiver<FrameMessage>, writer: &mut dyn Write, reporter: &Mutex<Option<&mut dyn ProgressReporter>>) -> CatResult<()> {
+        match format {
+            QuantizedFormat::Gif => self.write_frames_with::<RustEncoder<_>>(write_queue, writer, reporter),
+            QuantizedFormat::Apng => self.write_frames_with::<ApngEncoder<_>>(write_queue, writer, reporter),
+        }
+    }
+
     #[inline(never)]
-    fn write_frames(&self, write_queue: Receiver<FrameMessage>, writer: &mut dyn Write, reporter: &Mutex<Option<&mut dyn ProgressReporter>>) -> CatResult<()> {
+    fn write_frames_with<'w, E: FrameEncoder<&'w mut dyn Write>>(&self, write_queue: Receiver<FrameMessage>, writer: &'w mut dyn Write, reporter: &Mutex<Option<&mut dyn ProgressReporter>>) -> CatResult<()> {
         let (lzw_queue, lzw_recv) = ordqueue_new(2);
-        minipool::new_scope((if self.settings.s.fast || self.settings.gifsicle_loss() > 0 { 3 } else { 1 }).try_into().unwrap(), "lzw", move || {
-            let mut pts_in_delay_units = 0_u64;
+        // deflate of APNG is slower than LZW
+        let compress_threads = if self.settings.fast || self.settings.gifsicle_loss() > 0 || self.settings.format != OutputFormat::Gif { 3 } else { 1 };
+        minipool::new_scope(compress_threads.try_into().unwrap(), "lzw", move || {
+            let mut timing = DelayRounding::new(self.settings.timing, 100, 2, 30000);
 
             let written = Rc::new(Cell::new(0));
-            let mut enc = RustEncoder::new(writer, written.clone());
+            let mut enc = E::new(writer, written.clone());
 
             let mut n_done = 0;
             for tmp in lzw_recv {
-                let (end_pts, ordinal_frame_number, frame, screen_width, screen_height): (f64, _, _, _, _) = tmp;
+                let (end_pts, ordinal_frame_number, subframes, frame, screen_width, screen_height): (f64, _, Vec<_>, _, _, _) = tmp;
                 // delay=1 doesn't work, and it's too late to drop frames now
-                let delay = ((end_pts * 100_f64).round() as u64)
-                    .saturating_sub(pts_in_delay_units)
-                    .clamp(2, 30000) as u16;
-                pts_in_delay_units += u64::from(delay);
+                let delay = timing.delay(end_pts, ordinal_frame_number)? as u16;
 
-                enc.write_frame(frame, delay, screen_width, screen_height, &self.settings.s)?;
+                // parts of the same frame, shown all at once
+                for subframe in subframes {
+                    enc.write_frame(subframe, 0, screen_width, screen_height, &self.settings)?;
+                }
+                enc.write_frame(frame, delay, screen_width, screen_height, &self.settings)?;
 
                 let mut reporter_lock = reporter.lock().map_err(|_| Error::ThreadSend)?;
                 let reporter = reporter_lock.as_deref_mut().ok_or(Error::Aborted)?;
@@ -556,18 +1255,27 @@ impl Writer {
                 }
             }
             if n_done == 0 {
-                Err(Error::NoFrames)
-            } else {
-                Ok(())
+                return Err(Error::NoFrames);
+            }
+            enc.finish(&self.settings)?;
+            if let Some(reporter) = reporter.lock().map_err(|_| Error::ThreadSend)?.as_deref_mut() {
+                reporter.written_bytes(written.get());
+                reporter.timing(timing.report());
             }
+            Ok(())
         }, move |failed| {
-            for FrameMessage {frame, frame_index, ordinal_frame_number, end_pts, screen_width, screen_height } in write_queue {
+            for FrameMessage {subframes, frame, frame_index, ordinal_frame_number, end_pts, screen_width, screen_height } in write_queue {
                 if failed.load(Relaxed) {
                     return Err(Error::Aborted);
                 }
 
-                let frame = RustEncoder::<&mut dyn std::io::Write>::compress_frame(frame, &self.settings)?;
-                lzw_queue.send(frame_index, (end_pts, ordinal_frame_number, frame, screen_width, screen_height))?;
+                let mut subframes = subframes.into_iter().chain(Some(frame)).enumerate().map(|(n, f)| if frame_index == 0 && n == 0 {
+                    E::compress_first_frame(f, screen_width, screen_height, &self.settings)
+                } else {
+                    E::compress_frame(f, &self.settings)
+                }).collect::<CatResult<Vec<_>>>()?;
+                let frame = subframes.pop().ok_or(Error::ThreadSend)?;
+                lzw_queue.send(frame_index, (end_pts, ordinal_frame_number, subframes, frame, screen_width, screen_height))?;
             }
             Ok(())
         })
@@ -575,12 +1283,19 @@ impl Writer {
 
     /// Start writing frames. This function will not return until the [`Collector`] is dropped.
     ///
+    /// If you'd rather not manage the threads, see [`StreamingEncoder`].
+    ///
     /// `outfile` can be any writer, such as `File` or `&mut Vec`.
     ///
     /// `ProgressReporter.increase()` is called each time a new frame is being written.
+    ///
+    /// If [`Settings::max_size`] is set, nothing is written until the whole animation has been encoded.
     #[inline]
     pub fn write<W: Write>(mut self, mut writer: W, reporter: &mut dyn ProgressReporter) -> GifResult<()> {
         let decode_queue_recv = self.queue_iter.take().ok_or(Error::Aborted)?;
+        if let Some(max_size) = self.settings.max_size {
+            return self.write_with_max_size(decode_queue_recv, max_size, &mut writer, reporter);
+        }
         self.write_inner(decode_queue_recv, &mut writer, reporter)
     }
 
@@ -597,6 +1312,19 @@ impl Writer {
             let diff_thread = thread::Builder::new().name("diff".into()).spawn_scoped(s, move || {
                 self.make_diffs(diff_queue_recv, quant_queue, reporter)
             })?;
+            if !self.settings.is_quantized() {
+                let res0 = match self.settings.format {
+                    OutputFormat::WebP => self.write_truecolor::<AnimatedWebP<_>>(quant_queue_recv, writer, reporter),
+                    _ => self.write_truecolor::<ApngEncoder<_>>(quant_queue_recv, writer, reporter),
+                };
+                let res1 = resize_thread.join().map_err(handle_join_error)?;
+                let res2 = diff_thread.join().map_err(handle_join_error)?;
+                return combine_res(res0, combine_res(res1, res2));
+            }
+            let format = match self.settings.format {
+                OutputFormat::Apng => QuantizedFormat::Apng,
+                _ => QuantizedFormat::Gif,
+            };
             let (remap_queue, remap_queue_recv) = ordqueue_new(0);
             let quant_thread = thread::Builder::new().name("quant".into()).spawn_scoped(s, move || {
                 self.quantize_frames(quant_queue_recv, remap_queue)
@@ -605,7 +1333,7 @@ impl Writer {
             let remap_thread = thread::Builder::new().name("remap".into()).spawn_scoped(s, move || {
                 self.remap_frames(remap_queue_recv, write_queue)
             })?;
-            let res0 = self.write_frames(write_queue_recv, writer, reporter);
+            let res0 = self.write_frames(format, write_queue_recv, writer, reporter);
             let res1 = resize_thread.join().map_err(handle_join_error)?;
             let res2 = diff_thread.join().map_err(handle_join_error)?;
             let res3 = quant_thread.join().map_err(handle_join_error)?;
@@ -614,60 +1342,130 @@ impl Writer {
         })
     }
 
-    /// Apply resizing and crate a blurred version for the diff/denoise phase
+    /// Apply cropping and resizing, and crate a blurred version for the diff/denoise phase
     fn make_resize(&self, inputs: Receiver<InputFrame>, diff_queue: OrdQueue<InputFrameResized>) -> CatResult<()> {
-        minipool::new_scope(self.settings.max_threads.min(if self.settings.s.fast || self.settings.extra_effort { 6 } else { 4 }.try_into()?), "resize", move || {
+        // borders are found in the first frames, so these have to be decoded before anything can be cropped
+        let (crop, sample) = if self.settings.crop == Crop::Auto {
+            let sample = inputs.iter().take(crop::AUTO_CROP_SAMPLE_FRAMES)
+                .map(|f| Ok((f.frame_index, f.frame.into_pixels()?, f.time)))
+                .collect::<CatResult<Vec<_>>>()?;
+            let images: Vec<_> = sample.iter().map(|(_, image, _)| image.as_ref()).collect();
+            (crop::detect_borders(&images), sample)
+        } else {
+            // the first frame sets the size of the canvas
+            let first = match inputs.recv() {
+                Ok(f) => vec![(f.frame_index, f.frame.into_pixels()?, f.time)],
+                Err(_) => Vec::new(),
+            };
+            (self.settings.crop, first)
+        };
+        let Some((_, first_frame, _)) = sample.first() else {
+            return Ok(());
+        };
+        let (_, _, canvas_width, canvas_height) = crop.area(first_frame.width(), first_frame.height())?;
+        let canvas = (canvas_width, canvas_height);
+
+        let sample_queue = diff_queue.clone();
+        minipool::new_scope(self.max_threads.min(if self.settings.fast || self.settings.extra_effort { 6 } else { 4 }.try_into()?), "resize", move || {
+            for (frame_index, image, time) in sample {
+                sample_queue.send(frame_index, self.resize_frame(frame_index, image, time, crop, canvas)?)?;
+            }
             Ok(())
         }, move |abort| {
             for frame in inputs {
                 if abort.load(Relaxed) {
                     return Err(Error::Aborted);
                 }
-                let image = match frame.frame {
-                    FrameSource::Pixels(image) => image,
-                    #[cfg(feature = "png")]
-                    FrameSource::PngData(data) => {
-                        let image = lodepng::decode32(&data)
-                            .map_err(|err| Error::PNG(format!("Can't load PNG: {err}")))?;
-                        Img::new(image.buffer, image.width, image.height)
-                    },
-                    #[cfg(feature = "png")]
-                    FrameSource::Path(path) => {
-                        let image = lodepng::decode32_file(&path)
-                            .map_err(|err| Error::PNG(format!("Can't load {}: {err}", path.display())))?;
-                        Img::new(image.buffer, image.width, image.height)
-                    },
-                };
-                let resized = resized_binary_alpha(image, self.settings.s.width, self.settings.s.height, self.settings.matte)?;
-                let frame_blurred = if self.settings.extra_effort { smart_blur(resized.as_ref()) } else { less_smart_blur(resized.as_ref()) };
-                diff_queue.send(frame.frame_index, InputFrameResized {
-                    original_index: frame.frame_index,
-                    frame: resized,
-                    frame_blurred,
-                    presentation_timestamp: frame.presentation_timestamp,
-                })?;
+                let image = frame.frame.into_pixels()?;
+                diff_queue.send(frame.frame_index, self.resize_frame(frame.frame_index, image, frame.time, crop, canvas)?)?;
             }
             Ok(())
         })
     }
 
+    /// Frames that don't have the size of the first frame are fitted to it before they're resized, so that they all end up the same size
+    fn resize_frame(&self, frame_index: usize, image: ImgVec<RGBA8>, time: FrameTime, crop: Crop, (canvas_width, canvas_height): (usize, usize)) -> CatResult<InputFrameResized> {
+        let image = crop.apply(image)?;
+        let resized = if image.width() == canvas_width && image.height() == canvas_height {
+            resized_binary_alpha(image, &self.settings)?
+        } else {
+            canvas::fit(image, canvas_width, canvas_height, &self.settings)?
+        };
+        let frame_blurred = self.blurred(resized.as_ref());
+        Ok(InputFrameResized {
+            original_index: frame_index,
+            frame: resized,
+            frame_blurred,
+            time,
+        })
+    }
+
+    fn blurred(&self, image: ImgRef<RGBA8>) -> ImgVec<RGB8> {
+        if self.settings.lossless != Lossless::Off {
+            // the denoiser in exact mode doesn't use it
+            ImgVec::new(image.pixels().map(|px| px.rgb()).collect(), image.width(), image.height())
+        } else if self.settings.extra_effort {
+            smart_blur(image)
+        } else {
+            less_smart_blur(image)
+        }
+    }
+
     /// Find differences between frames, and compute importance maps
     fn make_diffs(&self, mut inputs: OrdQueueIter<InputFrameResized>, diffs: Sender<DiffMessage>, reporter: &Mutex<Option<&mut dyn ProgressReporter>>) -> CatResult<()> {
         let first_frame = inputs.next().ok_or(Error::NoFrames)?;
 
-        let mut last_frame_duration = if first_frame.presentation_timestamp > 1. / 100. {
+        let mut last_frame_duration = match first_frame.time {
             // this is gifski's weird rule that a non-zero first-frame pts
             // shifts the whole anim and is the delay of the last frame
-            LastFrameDuration::FixedOffset(first_frame.presentation_timestamp)
-        } else {
-            LastFrameDuration::FrameRate(0.)
+            FrameTime::Pts(first_pts) if first_pts > 1. / 100. => LastFrameDuration::FixedOffset(first_pts),
+            _ => LastFrameDuration::FrameRate(0.),
         };
 
-        let mut denoiser = Denoiser::new(first_frame.frame.width(), first_frame.frame.height(), self.settings.motion_quality)?;
+        // all frames must have the size of the first one
+        let (canvas_width, canvas_height) = (first_frame.frame.width(), first_frame.frame.height());
+        let new_denoiser = || -> CatResult<_> {
+            let mut denoiser = Denoiser::new(canvas_width, canvas_height, self.settings.motion_quality())?;
+            if self.settings.lossless != Lossless::Off {
+                denoiser.set_exact();
+            }
+            Ok(denoiser)
+        };
+        let mut denoiser = new_denoiser()?;
+        let mut prev_frame_blurred: Option<ImgVec<RGB8>> = None;
 
         let mut ordinal_frame_number = 0;
         let mut next_original_index_expected = 0;
         let mut last_frame_pts = 0.;
+        let mut last_frame_end_pts = 0.;
+        let mut last_50fps_slot = None;
+        let mut idle_limit = self.settings.idle_limit.map(IdleLimit::new);
+        // frame from the part of a still stretch that has been cut off
+        let mut idle_frame: Option<DiffMessage> = None;
+        let mut send_denoised = |importance_map: ImgVec<u8>, image, (ordinal_frame_number, mut pts, frame_duration, scene_cut): (_, _, f64, _)| -> CatResult<()> {
+            let (mut importance_map, ..) = importance_map.into_contiguous_buf();
+            let mut past_idle_limit = false;
+            if let Some(idle_limit) = &mut idle_limit {
+                (pts, past_idle_limit) = idle_limit.retime(pts, &importance_map);
+                // the frame that has been cut off may have changed something, and it has to be drawn by this frame
+                if let Some(cut) = idle_frame.take() {
+                    importance_map.iter_mut().zip(cut.importance_map).for_each(|(i, cut)| *i = (*i).max(cut));
+                }
+            }
+            let frame = DiffMessage {
+                importance_map,
+                ordinal_frame_number,
+                image,
+                pts, frame_duration: frame_duration.max(1. / 100.),
+                scene_cut,
+            };
+            if past_idle_limit {
+                idle_frame = Some(frame);
+            } else {
+                diffs.send(frame)?;
+            }
+            Ok(())
+        };
         let mut next_frame = Some(first_frame);
         loop {
             // NB! There are two interleaved loops here:
@@ -679,7 +1477,8 @@ impl Writer {
 
             ////////////////////// Feed denoiser: /////////////////////
 
-            if let Some(InputFrameResized { frame, frame_blurred, original_index, presentation_timestamp: raw_pts }) = next_frame {
+            if let Some(InputFrameResized { frame, frame_blurred, original_index, time }) = next_frame {
+
                 if original_index != next_original_index_expected {
                     if let Some(r) = &mut *reporter.lock().map_err(|_| Error::ThreadSend)? {
                         r.error(format!("expected frame_number {next_original_index_expected}, got {original_index}"));
@@ -688,21 +1487,57 @@ impl Writer {
                 next_original_index_expected = original_index + 1;
                 ordinal_frame_number += 1;
 
-                let mut pts = raw_pts - last_frame_duration.shift_every_pts_by();
-                if pts < last_frame_pts {
-                    if let Some(r) = &mut *reporter.lock().map_err(|_| Error::ThreadSend)? {
-                        r.error(format!("expected frame_number {original_index} to have pts > {last_frame_pts:0.3}, got {raw_pts:0.3}"));
-                    }
-                    pts = last_frame_pts;
-                }
-                else if let LastFrameDuration::FrameRate(duration) = &mut last_frame_duration {
-                    *duration = pts - last_frame_pts;
-                }
+                let (mut pts, mut frame_duration) = match time {
+                    FrameTime::Pts(raw_pts) => {
+                        let mut pts = raw_pts - last_frame_duration.shift_every_pts_by();
+                        if pts < last_frame_pts {
+                            if let Some(r) = &mut *reporter.lock().map_err(|_| Error::ThreadSend)? {
+                                r.error(format!("expected frame_number {original_index} to have pts > {last_frame_pts:0.3}, got {raw_pts:0.3}"));
+                            }
+                            pts = last_frame_pts;
+                        }
+                        else if let LastFrameDuration::FrameRate(duration) = &mut last_frame_duration {
+                            *duration = pts - last_frame_pts;
+                        }
+                        (pts, last_frame_duration.value())
+                    },
+                    FrameTime::Duration(duration) => {
+                        // pts in whole centiseconds, so that write_frames gets exactly these delays back
+                        let pts_cs = (last_frame_end_pts * 100_f64).round();
+                        let duration_cs = (duration * 100_f64).round().max(0.);
+                        (pts_cs / 100., duration_cs / 100.)
+                    },
+                };
                 last_frame_pts = pts;
+                last_frame_end_pts = pts + frame_duration;
+
+                let drop_frame = if self.settings.timing == TimingMode::Max50Fps {
+                    // GIF's delays can't be shorter than 2cs, so frames landing in the same 2cs slot are dropped
+                    let slot = (pts * 50.).round();
+                    pts = slot / 50.;
+                    frame_duration = (frame_duration * 50.).round().max(1.) / 50.;
+                    last_50fps_slot.replace(slot) == Some(slot)
+                } else {
+                    false
+                };
 
-                denoiser.push_frame(frame.as_ref(), frame_blurred.as_ref(), (ordinal_frame_number, pts, last_frame_duration)).map_err(|_| {
-                    Error::WrongSize(format!("Frame {ordinal_frame_number} has wrong size ({}×{})", frame.width(), frame.height()))
-                })?;
+                // the denoiser doesn't need to know about dropped frames,
+                // and their ordinal_frame_number is counted as merged when the next frame is written
+                if !drop_frame {
+                    let scene_cut = prev_frame_blurred.as_ref().is_some_and(|prev| is_scene_cut(prev.as_ref(), frame_blurred.as_ref()));
+                    if scene_cut {
+                        // the denoiser's lookahead would smear pixels of the previous scene into the new one
+                        denoiser.flush();
+                        while let Denoised::Frame { importance_map, frame, meta } = denoiser.pop() {
+                            send_denoised(importance_map, frame, meta)?;
+                        }
+                        denoiser = new_denoiser()?;
+                    }
+                    denoiser.push_frame(frame.as_ref(), frame_blurred.as_ref(), (ordinal_frame_number, pts, frame_duration, scene_cut)).map_err(|_| {
+                        Error::WrongSize(format!("Frame {ordinal_frame_number} has wrong size ({}×{})", frame.width(), frame.height()))
+                    })?;
+                    prev_frame_blurred = Some(frame_blurred);
+                }
             } else {
                 denoiser.flush();
             }
@@ -715,52 +1550,79 @@ impl Writer {
                     break;
                 },
                 Denoised::NotYet => {},
-                Denoised::Frame { importance_map, frame: image, meta: (ordinal_frame_number, pts, last_frame_duration) } => {
-                    let (importance_map, ..) = importance_map.into_contiguous_buf();
-                    diffs.send(DiffMessage {
-                        importance_map,
-                        ordinal_frame_number,
-                        image,
-                        pts, frame_duration: last_frame_duration.value().max(1. / 100.),
-                    })?;
-                },
+                Denoised::Frame { importance_map, frame, meta } => send_denoised(importance_map, frame, meta)?,
             }
             next_frame = inputs.next();
         }
 
+        if let (Some(mut last), Some(idle_limit)) = (idle_frame, &idle_limit) {
+            last.frame_duration = last.frame_duration.min(idle_limit.max_idle());
+            diffs.send(last)?;
+        }
+
         Ok(())
     }
 
     fn quantize_frames(&self, inputs: Receiver<DiffMessage>, remap_queue: OrdQueue<RemapMessage>) -> CatResult<()> {
-        minipool::new_channel(self.settings.max_threads.min(4.try_into()?), "quant", move |quant_queue| {
+        minipool::new_channel(self.max_threads.min(4.try_into()?), "quant", move |quant_queue| {
         let mut inputs = inputs.into_iter();
-        let next_frame = inputs.next().ok_or(Error::NoFrames)?;
+        let sample: Vec<_> = if self.settings.global_palette {
+            inputs.by_ref().take(GLOBAL_PALETTE_SAMPLE_FRAMES).collect()
+        } else {
+            Vec::new()
+        };
+        let global_pal = if !sample.is_empty() {
+            let exact = if self.settings.lossless != Lossless::Off {
+                // all frames after the first need transparency for unchanged pixels
+                match self.exact_palette(sample.iter().map(|f| f.image.as_ref()), true) {
+                    Ok(pal) => Some(pal),
+                    Err(n) if self.settings.lossless == Lossless::Required => return Err(Error::TooManyColors(sample[n].ordinal_frame_number)),
+                    Err(_) => None,
+                }
+            } else {
+                None
+            };
+            Some(Arc::<[RGBA8]>::from(match exact {
+                Some(pal) => pal,
+                None => self.global_palette(&sample)?,
+            }))
+        } else {
+            None
+        };
+        let mut frames = MergedFrames::new(self.settings.merge_threshold, sample.into_iter().chain(inputs));
 
-        let DiffMessage {image: first_frame, ..} = &next_frame;
+        let DiffMessage {image: first_frame, ..} = frames.peek().ok_or(Error::NoFrames)?;
         let first_frame_has_transparency = first_frame.pixels().any(|px| px.a < 128);
 
         let mut prev_frame_keeps = false;
         let mut frame_index = 0;
-        let mut importance_map = None;
-        let mut next_frame = Some(next_frame);
-        while let Some(DiffMessage { image, pts, frame_duration, ordinal_frame_number, importance_map: new_importance_map }) = next_frame {
-            next_frame = inputs.next();
-
-            if importance_map.is_none() {
-                importance_map = Some(new_importance_map);
-            }
-
-            let dispose = if let Some(DiffMessage { image: next_image, .. }) = &next_frame {
-                // Skip identical frames
-                if next_image.as_ref() == image.as_ref() {
-                    // this keeps importance_map of the previous frame in the identical-frame series
-                    // (important, because subsequent identical frames have all-zero importance_map and would be dropped too)
-                    continue;
-                }
-
-                // If the next frame becomes transparent, this frame has to clear to bg for it
-                if next_image.pixels().zip(image.pixels()).any(|(next, curr)| next.a < curr.a) {
+        let mut keyframe = false;
+        // global palette doesn't need it, and exact or ordered remapping doesn't use liq's palettes the same way
+        let can_reuse_palette = global_pal.is_none() && self.settings.lossless == Lossless::Off && self.settings.dither_stability == 0;
+        let can_use_high_color = self.settings.high_color && global_pal.is_none() && self.settings.lossless == Lossless::Off && self.settings.format == OutputFormat::Gif;
+        let mut palette_colors: Option<ColorBins> = None;
+        // what's on screen once the last sent frame has been disposed, if it's known
+        let mut screen_image: Option<ImgVec<RGBA8>> = None;
+        while let Some(frame) = frames.next() {
+            let end_pts = frames.end_pts(&frame);
+            let DiffMessage { image, ordinal_frame_number, importance_map, scene_cut, .. } = frame;
+            let next_frame = frames.peek();
+
+            // kept for the next frame if this one is skipped
+            keyframe |= scene_cut;
+
+            let dispose = if let Some(DiffMessage { image: next_image, scene_cut: next_scene_cut, .. }) = next_frame {
+                // If the next frame becomes transparent, this frame has to clear to bg for it.
+                // A new scene is drawn from scratch, without reusing anything of the previous one.
+                if *next_scene_cut || next_image.pixels().zip(image.pixels()).any(|(next, curr)| next.a < curr.a) {
                     DisposalMethod::Background
+                } else if screen_image.as_ref().is_some_and(|restored| {
+                    // This frame is a transient overlay (like a tooltip or a cursor) if the next frame is closer to what was there before it.
+                    // Ties keep the frame, because restoring makes decoders save a copy of the screen, and some handle it poorly.
+                    restored.pixels().zip(next_image.pixels()).all(|(restored, next)| next.a >= restored.a) &&
+                        changed_area(restored.as_ref(), next_image.as_ref()) < changed_area(image.as_ref(), next_image.as_ref())
+                }) {
+                    DisposalMethod::Previous
                 } else {
                     DisposalMethod::Keep
                 }
@@ -772,50 +1634,112 @@ impl Writer {
                 DisposalMethod::Keep
             };
 
-            let importance_map = importance_map.take().ok_or(Error::ThreadSend)?; // always set at the beginning
-
             if !prev_frame_keeps || importance_map.iter().any(|&px| px > 0) {
-                let end_pts = if let Some(&DiffMessage { pts: next_pts, .. }) = next_frame.as_ref() {
-                    next_pts
-                } else {
-                    pts + frame_duration
-                };
                 debug_assert!(end_pts > 0.);
 
+                let is_keyframe = frame_index == 0 || keyframe;
+                // tiles are drawn with Keep, so Background disposal wouldn't clear all of them
+                let high_color = can_use_high_color && is_keyframe && dispose == DisposalMethod::Keep;
+                let reuse_palette = can_reuse_palette && !is_keyframe &&
+                    palette_colors.as_ref().is_some_and(|colors| colors.covers(image.as_ref(), &importance_map, prev_frame_keeps));
+                if high_color {
+                    // there's no single palette to reuse
+                    palette_colors = None;
+                } else if can_reuse_palette && !reuse_palette {
+                    // palette of the first frame has no transparent color to reuse
+                    palette_colors = (frame_index > 0 || first_frame_has_transparency)
+                        .then(|| ColorBins::new(image.as_ref(), &importance_map, prev_frame_keeps));
+                }
+                let kept_image = (dispose == DisposalMethod::Keep && next_frame.is_some()).then(|| image.clone());
+
                 quant_queue.send(QuantizeMessage {
                     image,
                     ordinal_frame_number, frame_index,
                     first_frame_has_transparency,
                     importance_map, prev_frame_keeps, dispose, end_pts,
                     has_next_frame: next_frame.is_some(),
+                    keyframe: is_keyframe,
+                    reuse_palette,
+                    high_color,
+                    global_pal: global_pal.clone(),
                 })?;
 
+                keyframe = false;
                 frame_index += 1;
+                // the importance map is relative to this frame, which isn't on screen after it's disposed to previous
                 prev_frame_keeps = dispose == DisposalMethod::Keep;
+                if dispose != DisposalMethod::Previous {
+                    screen_image = kept_image;
+                }
             }
         }
         Ok(())
-        }, move |QuantizeMessage { end_pts, mut image, importance_map, ordinal_frame_number, frame_index, dispose, first_frame_has_transparency, prev_frame_keeps, has_next_frame }| {
+        }, move |QuantizeMessage { end_pts, mut image, importance_map, ordinal_frame_number, frame_index, dispose, first_frame_has_transparency, prev_frame_keeps, has_next_frame, keyframe, reuse_palette, high_color, global_pal }| {
+            let lossless = self.settings.lossless != Lossless::Off;
+            let stable_dither = self.settings.dither_stability > 0 && !lossless;
+            // the transparent color takes a palette entry, so a frame with 256 colors may fit only if it's drawn whole
+            let unmasked_image = (lossless && prev_frame_keeps && global_pal.is_none()).then(|| image.clone());
             if prev_frame_keeps {
                 // if denoiser says the background didn't change, then believe it
                 // (except higher quality settings, which try to improve it every time)
-                let bg_keep_likelihood = u32::from(self.settings.s.quality.saturating_sub(80) / 4);
-                if self.settings.s.fast || (self.settings.s.quality < 100 && (frame_index % 5) >= bg_keep_likelihood) {
-                    image.pixels_mut().zip(&importance_map).filter(|&(_, &m)| m == 0).for_each(|(px, _)| *px = RGBA8::new(0,0,0,0));
+                let bg_keep_likelihood = u32::from(self.settings.quality.saturating_sub(80) / 4);
+                // stable dithering keeps pixels that changed a little too (the denoiser's importance of a barely visible change is 5)
+                let keep_below = if stable_dither { 1 + self.settings.dither_stability / 4 } else { 1 };
+                // in lossless mode unchanged pixels are exactly the same
+                if lossless || stable_dither || self.settings.fast || (self.settings.quality < 100 && (frame_index % 5) >= bg_keep_likelihood) {
+                    image.pixels_mut().zip(&importance_map).filter(|&(_, &m)| m < keep_below).for_each(|(px, _)| *px = RGBA8::new(0,0,0,0));
                 }
             }
 
             let needs_transparency = frame_index > 0 || (frame_index == 0 && first_frame_has_transparency);
-            let (liq, remap, liq_image, out_buf) = self.quantize(image, &importance_map, frame_index == 0, needs_transparency, prev_frame_keeps)?;
+            let exact_pal = match &global_pal {
+                // nothing to quantize, and liq can't make a palette when every color of the image is one of its fixed colors
+                Some(global_pal) if has_all_colors(image.as_ref(), global_pal) => Some(global_pal.to_vec()),
+                _ if lossless => {
+                    let pal = if global_pal.is_some() {
+                        None
+                    } else {
+                        self.exact_palette(std::iter::once(image.as_ref()), needs_transparency).ok().or_else(|| {
+                            let whole = unmasked_image.filter(|img| img.pixels().all(|px| px.a > 0))?;
+                            let pal = self.exact_palette(std::iter::once(whole.as_ref()), false).ok()?;
+                            image = whole;
+                            Some(pal)
+                        })
+                    };
+                    if pal.is_none() && self.settings.lossless == Lossless::Required {
+                        return Err(Error::TooManyColors(ordinal_frame_number));
+                    }
+                    pal
+                },
+                _ => None,
+            };
+            let quantized = if let Some(pal) = exact_pal {
+                Quantized::Exact { image, pal }
+            } else if high_color {
+                let tiles = self.quantize_tiles(image.as_ref(), &importance_map)?;
+                Quantized::Tiled { tiles, width: image.width(), height: image.height() }
+            } else if reuse_palette {
+                let (liq, remap, liq_image, out_buf) = self.quantize(image.clone(), &importance_map, keyframe, needs_transparency, prev_frame_keeps, global_pal.as_deref())?;
+                Quantized::ReusePalette { image, fallback: Box::new(LiqQuantized { liq, remap, liq_image, out_buf }) }
+            } else if stable_dither {
+                // only the palette is used, because liq's error diffusion makes a different pattern every frame
+                let (liq, mut remap, liq_image, out_buf) = self.quantize(image.clone(), &importance_map, keyframe, needs_transparency, prev_frame_keeps, global_pal.as_deref())?;
+                match dither::with_transparent_color(remap.palette_vec(), image.as_ref()) {
+                    Some(pal) => Quantized::Ordered { image, pal },
+                    None => Quantized::Liq(Box::new(LiqQuantized { liq, remap, liq_image, out_buf })),
+                }
+            } else {
+                let (liq, remap, liq_image, out_buf) = self.quantize(image, &importance_map, keyframe, needs_transparency, prev_frame_keeps, global_pal.as_deref())?;
+                Quantized::Liq(Box::new(LiqQuantized { liq, remap, liq_image, out_buf }))
+            };
 
             Ok(remap_queue.send(frame_index as usize, RemapMessage {
                 ordinal_frame_number,
                 end_pts,
                 dispose,
-                liq, remap,
-                liq_image,
-                out_buf,
+                quantized,
                 has_next_frame,
+                global_pal,
             })?)
         })
     }
@@ -823,34 +1747,108 @@ impl Writer {
     fn remap_frames(&self, mut inputs: OrdQueueIter<RemapMessage>, write_queue: Sender<FrameMessage>) -> CatResult<()> {
         let mut frame_index = 0;
         let first_frame = inputs.next().ok_or(Error::NoFrames)?;
-        let mut screen = gif_dispose::Screen::new(first_frame.liq_image.width(), first_frame.liq_image.height(), None);
+        let (width, height) = first_frame.quantized.size();
+        let mut screen = gif_dispose::Screen::new(width, height, None);
 
         #[cfg(debug_assertions)]
-        let mut debug_screen = gif_dispose::Screen::new(first_frame.liq_image.width(), first_frame.liq_image.height(), None);
+        let mut debug_screen = gif_dispose::Screen::new(width, height, None);
 
+        let mut prev_pal: Option<Vec<RGBA8>> = None;
         let mut next_frame = Some(first_frame);
-        while let Some(RemapMessage {ordinal_frame_number, end_pts, dispose, liq, remap, liq_image, out_buf, has_next_frame}) = next_frame {
+        while let Some(RemapMessage {ordinal_frame_number, end_pts, dispose, quantized, has_next_frame, global_pal}) = next_frame {
             let pixels = screen.pixels_rgba();
             let screen_width = pixels.width() as u16;
             let screen_height = pixels.height() as u16;
             let mut screen_after_dispose = screen.dispose_only();
 
-            let (mut image8, image8_pal) = {
+            let (mut image8, mut image8_pal) = {
                 let bg = if frame_index != 0 { Some(screen_after_dispose.pixels_rgba()) } else { None };
-                self.remap(liq, remap, liq_image, bg, out_buf)?
+                match quantized {
+                    Quantized::Liq(q) => {
+                        let LiqQuantized { liq, remap, liq_image, out_buf } = *q;
+                        self.remap(liq, remap, liq_image, bg, out_buf)?
+                    },
+                    Quantized::Exact { image, pal } => remap_exact(image.as_ref(), pal, bg),
+                    Quantized::Ordered { image, pal } => dither::remap_ordered(image.as_ref(), pal, bg, self.settings.ordered_dithering_level()),
+                    Quantized::ReusePalette { image, fallback } => {
+                        // finding the nearest colors is much faster than liq's remapping in this serial stage
+                        let pal = prev_pal.take()
+                            .filter(|pal| has_close_colors(image.as_ref(), pal))
+                            .and_then(|pal| dither::with_transparent_color(pal, image.as_ref()));
+                        if let Some(pal) = pal {
+                            dither::remap_ordered(image.as_ref(), pal, bg, 0.)
+                        } else {
+                            let LiqQuantized { liq, remap, liq_image, out_buf } = *fallback;
+                            self.remap(liq, remap, liq_image, bg, out_buf)?
+                        }
+                    },
+                    Quantized::Tiled { tiles, .. } => {
+                        let (subframes, last) = highcolor::tiles_to_frames(tiles, dispose)?;
+                        let mut all = subframes.iter().chain(Some(&last));
+                        let first = all.next().ok_or(Error::NoFrames)?;
+                        screen_after_dispose.then_blit(Some(&first.pal), first.dispose, first.left, first.top, first.image.as_ref(), first.transparent_index)?;
+                        for f in all {
+                            screen.blit(Some(&f.pal), f.dispose, f.left, f.top, f.image.as_ref(), f.transparent_index)?;
+                        }
+                        #[cfg(debug_assertions)]
+                        for f in subframes.iter().chain(Some(&last)) {
+                            debug_screen.blit(Some(&f.pal), f.dispose, f.left, f.top, f.image.as_ref(), f.transparent_index)?;
+                        }
+
+                        // the next frame can't reuse a palette of only one tile
+                        prev_pal = None;
+                        write_queue.send(FrameMessage {
+                            frame_index,
+                            ordinal_frame_number,
+                            end_pts,
+                            screen_width,
+                            screen_height,
+                            subframes,
+                            frame: last,
+                        })?;
+                        frame_index += 1;
+                        next_frame = inputs.next();
+                        continue;
+                    },
+                }
             };
 
+            if let Some(global_pal) = &global_pal {
+                remap_to_global_palette(image8.as_mut(), &image8_pal, global_pal);
+                image8_pal = global_pal.to_vec();
+            }
+
+            prev_pal = Some(image8_pal.clone());
             let (image8_pal, transparent_index) = transparent_index_from_palette(image8_pal, image8.as_mut());
 
             #[cfg(debug_assertions)]
             debug_screen.blit(Some(&image8_pal), dispose, 0, 0, image8.as_ref(), transparent_index)?;
 
+            let mut subframes = Vec::new();
             let (left, top) = if frame_index != 0 && has_next_frame {
-                let (left, top, new_width, new_height) = trim_image(image8.as_ref(), &image8_pal, transparent_index, dispose, screen_after_dispose.pixels_rgba())
+                let trimmed = trim_image(image8.as_ref(), &image8_pal, transparent_index, dispose, screen_after_dispose.pixels_rgba())
                     .unwrap_or((0, 0, 1, 1));
+                // APNG's deflate doesn't have the same cost
+                let mut rects = if self.settings.split_frames && dispose == DisposalMethod::Keep && self.settings.format == OutputFormat::Gif {
+                    subframes::split_changed_area(image8.as_ref(), &image8_pal, transparent_index, screen_after_dispose.pixels_rgba(), trimmed)
+                } else {
+                    None
+                }.unwrap_or_default();
+                let (left, top, new_width, new_height) = rects.pop().unwrap_or(trimmed);
+                let crop = |(left, top, width, height): subframes::Rect| {
+                    let buf = image8.sub_image(left.into(), top.into(), width, height).to_contiguous_buf().0.into_owned();
+                    ImgVec::new(buf, width, height)
+                };
+                subframes = rects.into_iter().map(|rect| GIFFrame {
+                    left: rect.0,
+                    top: rect.1,
+                    image: crop(rect),
+                    pal: image8_pal.clone(),
+                    transparent_index,
+                    dispose,
+                }).collect();
                 if new_width != image8.width() || new_height != image8.height() {
-                    let new_buf = image8.sub_image(left.into(), top.into(), new_width, new_height).to_contiguous_buf().0.into_owned();
-                    image8 = ImgVec::new(new_buf, new_width, new_height);
+                    image8 = crop((left, top, new_width, new_height));
                 }
                 (left, top)
             } else {
@@ -858,10 +1856,19 @@ impl Writer {
                 (0, 0)
             };
 
-            screen_after_dispose.then_blit(Some(&image8_pal), dispose, left, top, image8.as_ref(), transparent_index)?;
+            if let Some((first, rest)) = subframes.split_first() {
+                screen_after_dispose.then_blit(Some(&first.pal), first.dispose, first.left, first.top, first.image.as_ref(), first.transparent_index)?;
+                for f in rest {
+                    screen.blit(Some(&f.pal), f.dispose, f.left, f.top, f.image.as_ref(), f.transparent_index)?;
+                }
+                screen.blit(Some(&image8_pal), dispose, left, top, image8.as_ref(), transparent_index)?;
+            } else {
+                screen_after_dispose.then_blit(Some(&image8_pal), dispose, left, top, image8.as_ref(), transparent_index)?;
+            }
 
+            // the same as drawing the whole frame
             #[cfg(debug_assertions)]
-            debug_assert!(debug_screen.pixels_rgba() == screen.pixels_rgba(), "fr {ordinal_frame_number} {left}/{top} {}x{}", image8.width(), image8.height());
+            debug_assert!(debug_screen.pixels_rgba() == screen.pixels_rgba(), "fr {ordinal_frame_number} {left}/{top} {}x{} +{}", image8.width(), image8.height(), subframes.len());
 
             write_queue.send(FrameMessage {
                 frame_index,
@@ -869,6 +1876,7 @@ impl Writer {
                 end_pts,
                 screen_width,
                 screen_height,
+                subframes,
                 frame: GIFFrame {
                     left,
                     top,
@@ -885,6 +1893,161 @@ impl Writer {
     }
 }
 
+/// Maps colors of `Lossless` frames to their palette. Pixels that are already on the screen become transparent.
+fn remap_exact(image: ImgRef<RGBA8>, pal: Vec<RGBA8>, background: Option<ImgRef<RGBA8>>) -> (ImgVec<u8>, Vec<RGBA8>) {
+    let index: HashMap<RGBA8, u8> = pal.iter().enumerate().rev().map(|(i, &color)| (color, i as u8)).collect();
+    let transparent_index = index.get(&RGBA8::new(0, 0, 0, 0)).copied();
+    let mut out = Vec::with_capacity(image.width() * image.height());
+    let mut bg_rows = background.map(|bg| bg.rows());
+    for row in image.rows() {
+        let bg_row = bg_rows.as_mut().and_then(|rows| rows.next());
+        for (x, &px) in row.iter().enumerate() {
+            let is_on_screen = bg_row.is_some_and(|bg_row| bg_row[x] == px);
+            out.push_in_cap(match transparent_index {
+                Some(transparent_index) if px.a == 0 || is_on_screen => transparent_index,
+                _ => {
+                    let color = index.get(&px).copied();
+                    debug_assert!(color.is_some(), "{px:?} not in palette");
+                    color.unwrap_or(0)
+                },
+            });
+        }
+    }
+    (ImgVec::new(out, image.width(), image.height()), pal)
+}
+
+/// imagequant may reorder the fixed colors, so the indices need to be translated to the global palette
+fn remap_to_global_palette(mut image8: ImgRefMut<u8>, pal: &[RGBA8], global_pal: &[RGBA8]) {
+    let to_global: Vec<u8> = pal.iter().map(|&color| {
+        global_pal.iter().enumerate().min_by_key(|&(_, &g)| {
+            let diff = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+            diff(color.r, g.r) + diff(color.g, g.g) + diff(color.b, g.b) + 2 * diff(color.a, g.a)
+        }).map_or(0, |(idx, _)| idx as u8)
+    }).collect();
+    image8.pixels_mut().for_each(|px| *px = to_global.get(*px as usize).copied().unwrap_or(0));
+}
+
+fn has_all_colors(image: ImgRef<RGBA8>, pal: &[RGBA8]) -> bool {
+    let pal: HashSet<RGBA8> = pal.iter().copied().collect();
+    image.pixels().all(|px| px.a == 0 || pal.contains(&px))
+}
+
+/// Largest difference of any channel of any pixel. Colors of fully transparent pixels don't matter.
+/// Decides which frames are merged into the next one, according to `Settings::merge_threshold`
+struct FrameMerger {
+    threshold: u8,
+    /// Sum of differences of frames merged since the last kept frame
+    merged_difference: u32,
+}
+
+impl FrameMerger {
+    pub fn new(threshold: u8) -> Self {
+        Self { threshold, merged_difference: 0 }
+    }
+
+    /// `true` if `image` can be skipped, and `next` shown for its duration too.
+    /// Differences add up, so that a slow fade isn't merged into one frame.
+    pub fn merges(&mut self, image: ImgRef<RGBA8>, next: ImgRef<RGBA8>) -> bool {
+        if image == next {
+            return true;
+        }
+        if self.threshold > 0 {
+            let diff = u32::from(max_difference(image, next));
+            if self.merged_difference + diff <= u32::from(self.threshold) {
+                self.merged_difference += diff;
+                return true;
+            }
+        }
+        // the frame is kept, and starts a new run
+        self.merged_difference = 0;
+        false
+    }
+}
+
+/// Frames with the ones that can be skipped merged into the next frame, which is shown for their duration too
+pub(crate) struct MergedFrames<I> {
+    merger: FrameMerger,
+    inputs: I,
+    next: Option<DiffMessage>,
+}
+
+impl<I: Iterator<Item = DiffMessage>> MergedFrames<I> {
+    pub fn new(merge_threshold: u8, mut inputs: I) -> Self {
+        let next = inputs.next();
+        Self { merger: FrameMerger::new(merge_threshold), inputs, next }
+    }
+
+    /// The frame that `next()` is going to return
+    pub fn peek(&self) -> Option<&DiffMessage> {
+        self.next.as_ref()
+    }
+
+    /// When the frame returned by `next()` stops being displayed
+    pub fn end_pts(&self, frame: &DiffMessage) -> f64 {
+        self.next.as_ref().map_or(frame.pts + frame.frame_duration, |next| next.pts)
+    }
+}
+
+impl<I: Iterator<Item = DiffMessage>> Iterator for MergedFrames<I> {
+    type Item = DiffMessage;
+
+    fn next(&mut self) -> Option<DiffMessage> {
+        let mut frame = self.next.take()?;
+        for mut next in self.inputs.by_ref() {
+            // Skip identical and similar frames
+            if !self.merger.merges(frame.image.as_ref(), next.image.as_ref()) {
+                self.next = Some(next);
+                break;
+            }
+            // this keeps importance_map of the first frame in the identical-frame series
+            // (important, because subsequent identical frames have all-zero importance_map and would be dropped too)
+            next.importance_map = frame.importance_map;
+            next.scene_cut |= frame.scene_cut;
+            frame = next;
+        }
+        Some(frame)
+    }
+}
+
+fn max_difference(a: ImgRef<RGBA8>, b: ImgRef<RGBA8>) -> u8 {
+    a.pixels().zip(b.pixels()).map(|(a, b)| {
+        if a.a == 0 && b.a == 0 {
+            return 0;
+        }
+        a.r.abs_diff(b.r).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b)).max(a.a.abs_diff(b.a))
+    }).max().unwrap_or(0)
+}
+
+/// Area of the bounding box of pixels that differ, which is roughly how much of the next frame would have to be drawn.
+///
+/// It's used to pick the disposal that leaves less to redraw, as a cheap stand-in for the encoded size:
+/// GIF frames are rectangles, so the bounding box is what gets written. Unchanged pixels inside it become transparent,
+/// and compress well, so it overestimates sparse changes, but both candidates are measured the same way.
+fn changed_area(a: ImgRef<RGBA8>, b: ImgRef<RGBA8>) -> usize {
+    changed_bbox(a, b).map_or(0, |(_, _, width, height)| width * height)
+}
+
+/// `(left, top, width, height)` of pixels that differ, or `None` if the images are the same
+pub(crate) fn changed_bbox(a: ImgRef<RGBA8>, b: ImgRef<RGBA8>) -> Option<(usize, usize, usize, usize)> {
+    debug_assert_eq!(a.width(), b.width());
+    let mut top = None;
+    let mut bottom = 0;
+    let mut left = b.width();
+    let mut right = 0;
+    for (y, (a_row, b_row)) in a.rows().zip(b.rows()).enumerate() {
+        let Some(first) = a_row.iter().zip(b_row).position(|(a, b)| a != b) else {
+            continue;
+        };
+        let last = a_row.iter().zip(b_row).rposition(|(a, b)| a != b).unwrap_or(first);
+        top.get_or_insert(y);
+        bottom = y;
+        left = left.min(first);
+        right = right.max(last);
+    }
+    let top = top?;
+    Some((left, top, right + 1 - left, bottom + 1 - top))
+}
+
 fn transparent_index_from_palette(mut image8_pal: Vec<RGBA8>, mut image8: ImgRefMut<u8>) -> (Vec<RGB8>, Option<u8>) {
     // Palette may have multiple transparent indices :(
     let mut transparent_index = None;
@@ -921,28 +2084,32 @@ fn combine_res(res1: Result<(), Error>, res2: Result<(), Error>) -> Result<(), E
     }
 }
 
+/// Drawing the pixel wouldn't change what's on screen
+fn is_matching_pixel(px: u8, bg: RGBA8, image8_pal: &[RGB8], transparent_index: Option<u8>, dispose: DisposalMethod) -> bool {
+    if Some(px) == transparent_index {
+        if dispose == DisposalMethod::Keep || dispose == DisposalMethod::Previous {
+            // if dispose == keep, then transparent pixels do nothing, so they can be cropped out
+            // (and with dispose == previous, the whole area is restored afterwards anyway)
+            true
+        } else {
+            debug_assert_eq!(dispose, DisposalMethod::Background);
+            // if disposing to background, then transparent pixels paint transparency, so bg has to actually be transparent to match
+            bg.a == 0
+        }
+    } else {
+        let Some(pal_px) = image8_pal.get(px as usize) else {
+            debug_assert!(false, "{px} > {}", image8_pal.len());
+            return false;
+        };
+        pal_px.with_alpha(255) == bg
+    }
+}
+
 fn trim_image(mut image_trimmed: ImgRef<u8>, image8_pal: &[RGB8], transparent_index: Option<u8>, dispose: DisposalMethod, mut screen: ImgRef<RGBA8>) -> Option<(u16, u16, usize, usize)> {
     debug_assert_eq!(image_trimmed.width(), screen.width());
     debug_assert_eq!(image_trimmed.height(), screen.height());
 
-    let is_matching_pixel = move |px: u8, bg: RGBA8| -> bool {
-        if Some(px) == transparent_index {
-            if dispose == DisposalMethod::Keep {
-                // if dispose == keep, then transparent pixels do nothing, so they can be cropped out
-                true
-            } else {
-                debug_assert_eq!(dispose, DisposalMethod::Background);
-                // if disposing to background, then transparent pixels paint transparency, so bg has to actually be transparent to match
-                bg.a == 0
-            }
-        } else {
-            let Some(pal_px) = image8_pal.get(px as usize) else {
-                debug_assert!(false, "{px} > {}", image8_pal.len());
-                return false;
-            };
-            pal_px.with_alpha(255) == bg
-        }
-    };
+    let is_matching_pixel = move |px: u8, bg: RGBA8| is_matching_pixel(px, bg, image8_pal, transparent_index, dispose);
 
     let bottom = image_trimmed.rows().zip(screen.rows()).rev()
         .take_while(|(img_row, screen_row)| {
@@ -1031,3 +2198,90 @@ fn sendable() {
     is_send::<Collector>();
     is_send::<Writer>();
 }
+
+#[test]
+fn settings_builder() {
+    let s = Settings::builder().quality(80).motion_quality(70).matte(RGB8::new(1, 2, 3)).build().unwrap();
+    assert_eq!(80, s.quality);
+    assert_eq!(70, s.motion_quality());
+    assert_eq!(80, s.lossy_quality());
+    assert_eq!(Some(RGB8::new(1, 2, 3)), s.matte);
+    assert_eq!(0, s.merge_threshold);
+
+    assert!(matches!(Settings::builder().quality(0).build(), Err(Error::InvalidSettings(_))));
+    assert!(Settings::builder().lossy_quality(101).build().is_err());
+    assert!(Settings::builder().resize(Resize::Fit { width: Some(1 << 20), height: None }).build().is_err());
+    assert!(Settings::builder().resize(Resize::Scale(0.)).build().is_err());
+    assert!(Settings::builder().unsharp(f32::NAN).build().is_err());
+    assert!(Settings::builder().idle_limit(0.).build().is_err());
+    assert!(Settings::builder().dither_stability(101).build().is_err());
+    assert!(Settings::builder().crop(Crop::Rect { left: 0, top: 0, width: 0, height: 10 }).build().is_err());
+}
+
+#[test]
+fn changed_area_bbox() {
+    let a = ImgVec::new(vec![RGBA8::new(0, 0, 0, 255); 10 * 10], 10, 10);
+    let mut b = a.clone();
+    assert_eq!(0, changed_area(a.as_ref(), b.as_ref()));
+    b[(2_usize, 3_usize)] = RGBA8::new(255, 0, 0, 255);
+    assert_eq!(1, changed_area(a.as_ref(), b.as_ref()));
+    b[(5_usize, 1_usize)] = RGBA8::new(255, 0, 0, 255);
+    assert_eq!(4 * 3, changed_area(a.as_ref(), b.as_ref()));
+    assert_eq!(Some((2, 1, 4, 3)), changed_bbox(a.as_ref(), b.as_ref()));
+    assert_eq!(None, changed_bbox(a.as_ref(), a.as_ref()));
+}
+
+#[test]
+fn merged_differences_add_up() {
+    let frame = |v: u8| ImgVec::new(vec![RGBA8::new(v, v, v, 255); 4], 2, 2);
+    let mut merger = FrameMerger::new(3);
+    assert!(merger.merges(frame(0).as_ref(), frame(0).as_ref()));
+    assert!(merger.merges(frame(0).as_ref(), frame(2).as_ref()));
+    // 2 + 2 is over the threshold, even though each step is under it
+    assert!(!merger.merges(frame(2).as_ref(), frame(4).as_ref()));
+    assert!(merger.merges(frame(4).as_ref(), frame(7).as_ref()));
+    assert!(!FrameMerger::new(0).merges(frame(0).as_ref(), frame(1).as_ref()));
+}
+
+#[test]
+fn merged_frames() {
+    let frame = |n: usize, v: u8, scene_cut: bool| DiffMessage {
+        ordinal_frame_number: n + 1,
+        pts: n as f64, frame_duration: 1.,
+        image: ImgVec::new(vec![RGBA8::new(v, v, v, 255); 4], 2, 2),
+        importance_map: vec![n as u8; 4],
+        scene_cut,
+    };
+    let mut frames = MergedFrames::new(0, [frame(0, 0, false), frame(1, 9, true), frame(2, 9, false), frame(3, 0, false)].into_iter());
+    let first = frames.next().unwrap();
+    assert_eq!((1, 1.), (first.ordinal_frame_number, frames.end_pts(&first)));
+    // the identical frame is shown for the duration of both, and keeps what was special about the first one
+    let merged = frames.next().unwrap();
+    assert_eq!((3, 3.), (merged.ordinal_frame_number, frames.end_pts(&merged)));
+    assert!(merged.scene_cut);
+    assert_eq!(merged.importance_map, [1; 4]);
+    let last = frames.next().unwrap();
+    assert!(frames.peek().is_none());
+    assert_eq!(4., frames.end_pts(&last));
+    assert!(frames.next().is_none());
+}
+
+#[test]
+fn resize_plan() {
+    let whole = (0, 0, 800, 600);
+    assert_eq!((whole, (800, 600)), Resize::Auto.plan((800, 600)));
+    assert_eq!(((0, 0, 1600, 1200), (800, 600)), Resize::Auto.plan((1600, 1200)));
+    assert_eq!(((0, 0, 1600, 1200), (1600, 1200)), Resize::None.plan((1600, 1200)));
+    assert_eq!((whole, (400, 300)), Resize::Fit { width: Some(400), height: None }.plan((800, 600)));
+    assert_eq!((whole, (133, 100)), Resize::Fit { width: Some(400), height: Some(100) }.plan((800, 600)));
+    assert_eq!((whole, (800, 600)), Resize::Fit { width: Some(4000), height: Some(4000) }.plan((800, 600)));
+    assert_eq!(((100, 0, 600, 600), (100, 100)), Resize::Fill { width: 100, height: 100 }.plan((800, 600)));
+    assert_eq!((whole, (100, 100)), Resize::Exact { width: 100, height: 100 }.plan((800, 600)));
+    assert_eq!((whole, (1000, 1000)), Resize::Exact { width: 1000, height: 1000 }.plan((800, 600)));
+    assert_eq!((whole, (800, 100)), Resize::Stretch { width: 1000, height: 100 }.plan((800, 600)));
+    assert_eq!(Resize::Stretch { width: 1000, height: 100 }, Resize::from_width_height(Some(1000), Some(100)));
+    assert_eq!(Resize::Fit { width: None, height: Some(100) }, Resize::from_width_height(None, Some(100)));
+    assert_eq!(Resize::Auto, Resize::from_width_height(None, None));
+    assert_eq!((whole, (400, 300)), Resize::Scale(0.5).plan((800, 600)));
+    assert_eq!((whole, (400, 300)), Resize::MaxPixels(400 * 300).plan((800, 600)));
+}
@@ -52,7 +52,9 @@ pub fn new_scope<P, C, R>(num_threads: NonZeroU8, name: &str, waiter: P, consume
             failed.store(true, Relaxed);
             e
         });
-        handles.into_iter().try_for_each(|h| h.join().map_err(|_| Error::ThreadSend)?)?;
-        res
+        let joined = handles.into_iter().try_for_each(|h| h.join().map_err(|_| Error::ThreadSend)?);
+        // workers fail to send after the waiter has given up, so the waiter's error is the cause
+        let res = res?;
+        joined.map(|()| res)
     })
 }
@@ -0,0 +1,196 @@
+//! Re-optimization of existing GIF files, which keeps their palettes and pixels exactly
+
+use crate::error::CatResult;
+use crate::{is_matching_pixel, trim_image, Error, GifResult};
+use gif::DisposalMethod;
+use imgref::ImgVec;
+use rgb::RGB8;
+use std::io::{Read, Write};
+
+/// Frame that is written once it's known that the next frame isn't identical to it
+struct PendingFrame {
+    frame: gif::Frame<'static>,
+    /// How the animation looks like when this frame is displayed
+    pixels: ImgVec<rgb::RGBA8>,
+}
+
+/// Decodes a GIF and encodes it again, without quantizing it again.
+///
+/// Palettes and pixels stay the same. Identical frames are merged, frames are trimmed to the area that changes,
+/// and pixels that are already on screen are made transparent, which usually compresses better.
+///
+/// `lossy_quality` (1-100) applies lossy LZW compression if the `gifsicle` feature is enabled. `None` is lossless.
+pub fn optimize_gif<R: Read, W: Write>(input: R, output: W, lossy_quality: Option<u8>) -> GifResult<()> {
+    let mut gif_opts = gif::DecodeOptions::new();
+    gif_opts.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = gif_opts.read_info(input)?;
+
+    let global_pal = decoder.global_palette().map(|pal| pal.to_vec());
+    let mut in_screen = gif_dispose::Screen::new_decoder(&decoder);
+    let mut out_screen = gif_dispose::Screen::new_decoder(&decoder);
+    let mut enc = gif::Encoder::new(output, decoder.width(), decoder.height(), global_pal.as_deref().unwrap_or_default())?;
+    enc.set_repeat(decoder.repeat())?;
+    let loss = lossy_quality.map_or(0, crate::gifsicle_loss_for_quality);
+
+    let mut pending: Option<PendingFrame> = None;
+    while let Some(info) = decoder.next_frame_info()? {
+        // read_next_frame would clear the interlaced flag, and the output should keep it
+        let mut frame = info.clone();
+        let mut buffer = vec![0; decoder.buffer_size()];
+        decoder.read_into_buffer(&mut buffer)?;
+        frame.buffer = buffer.into();
+        in_screen.blit_frame(&frame)?;
+        let pixels = in_screen.pixels_rgba();
+        if let Some(prev) = &mut pending {
+            // The next frame would start from the same state either way
+            if is_keep(prev.frame.dispose) && is_keep(frame.dispose) && prev.pixels.as_ref() == pixels {
+                prev.frame.delay = prev.frame.delay.saturating_add(frame.delay);
+                continue;
+            }
+        }
+        let next = PendingFrame {
+            frame,
+            pixels: pixels.map_buf(|b| b.to_owned()),
+        };
+        if let Some(prev) = pending.replace(next) {
+            write_frame(&mut enc, &mut out_screen, prev, global_pal.as_deref(), loss)?;
+        }
+    }
+    let last = pending.ok_or(Error::NoFrames)?;
+    write_frame(&mut enc, &mut out_screen, last, global_pal.as_deref(), loss)?;
+    enc.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Keeps the original disposal method. Background disposal clears the whole area of the original frame, so such frames can't be trimmed.
+fn write_frame<W: Write>(enc: &mut gif::Encoder<W>, screen: &mut gif_dispose::Screen, PendingFrame { mut frame, pixels }: PendingFrame, global_pal: Option<&[u8]>, loss: u32) -> CatResult<()> {
+    let pal: Vec<RGB8> = frame.palette.as_deref().or(global_pal).ok_or(gif::EncodingError::Format(gif::EncodingFormatError::MissingColorPalette))?
+        .chunks_exact(3).map(|c| RGB8::new(c[0], c[1], c[2])).collect();
+    let (left, top) = (usize::from(frame.left), usize::from(frame.top));
+    let (width, height) = (usize::from(frame.width), usize::from(frame.height));
+    if left + width > pixels.width() || top + height > pixels.height() {
+        return Err(Error::WrongSize("frame is larger than the GIF's screen".into()));
+    }
+    let mut image8 = ImgVec::new(frame.buffer.to_vec(), width, height);
+
+    let mut screen_after_dispose = screen.dispose_only();
+    let bg = screen_after_dispose.pixels_rgba();
+    let bg = bg.sub_image(left, top, width, height);
+
+    // any unused color can be the transparent one, without changing the palette
+    let transparent_index = frame.transparent.or_else(|| {
+        let mut used = [false; 256];
+        image8.pixels().for_each(|px| used[px as usize] = true);
+        used[..pal.len().min(256)].iter().position(|&u| !u).map(|i| i as u8)
+    });
+    if let Some(transparent_index) = transparent_index {
+        for (px, bg) in image8.pixels_mut().zip(bg.pixels()) {
+            if is_matching_pixel(*px, bg, &pal, None, DisposalMethod::Keep) {
+                *px = transparent_index;
+            }
+        }
+    }
+
+    let (trim_left, trim_top, new_width, new_height) = if frame.dispose == DisposalMethod::Background {
+        (0, 0, width, height)
+    } else {
+        let dispose = if frame.dispose == DisposalMethod::Previous { DisposalMethod::Previous } else { DisposalMethod::Keep };
+        trim_image(image8.as_ref(), &pal, transparent_index, dispose, bg).unwrap_or((0, 0, 1, 1))
+    };
+    if new_width != width || new_height != height {
+        let buf = image8.sub_image(trim_left.into(), trim_top.into(), new_width, new_height).to_contiguous_buf().0.into_owned();
+        image8 = ImgVec::new(buf, new_width, new_height);
+    }
+    let transparent_index = transparent_index.filter(|&t| frame.transparent == Some(t) || image8.pixels().any(|px| px == t));
+
+    frame.left += trim_left;
+    frame.top += trim_top;
+    frame.width = new_width as u16;
+    frame.height = new_height as u16;
+    frame.transparent = transparent_index;
+
+    screen_after_dispose.then_blit(Some(&pal), frame.dispose, frame.left, frame.top, image8.as_ref(), transparent_index)?;
+    debug_assert!(screen.pixels_rgba() == pixels.as_ref());
+
+    frame.buffer = image8.into_buf().into();
+    // the decoder has put rows in the normal order
+    crate::encoderust::compress_pixels(&mut frame, &pal, loss)?;
+    enc.write_lzw_pre_encoded_frame(&frame)?;
+    Ok(())
+}
+
+fn is_keep(dispose: DisposalMethod) -> bool {
+    matches!(dispose, DisposalMethod::Keep | DisposalMethod::Any)
+}
+
+#[test]
+fn optimize_lossless() {
+    use rgb::RGBA8;
+
+    let pal = [0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 255];
+    let mut gif = Vec::new();
+    {
+        let mut enc = gif::Encoder::new(&mut gif, 8, 8, &pal).unwrap();
+        let mut buffer = vec![1; 64];
+        for n in 0..4 {
+            // the same frame twice, and a change in one corner, all written as the whole screen
+            if n == 2 {
+                buffer[0] = 2;
+            }
+            let mut frame = gif::Frame { width: 8, height: 8, delay: 10, buffer: buffer.clone().into(), ..gif::Frame::default() };
+            frame.make_lzw_pre_encoded();
+            enc.write_lzw_pre_encoded_frame(&frame).unwrap();
+        }
+    }
+
+    let mut out = Vec::new();
+    optimize_gif(&gif[..], &mut out, None).unwrap();
+
+    let mut screens = Vec::new();
+    for data in [&gif, &out] {
+        let mut gif_opts = gif::DecodeOptions::new();
+        gif_opts.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = gif_opts.read_info(&data[..]).unwrap();
+        let mut screen = gif_dispose::Screen::new_decoder(&decoder);
+        let mut frames: Vec<(u16, u16, Vec<RGBA8>)> = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            screen.blit_frame(frame).unwrap();
+            frames.push((frame.delay, frame.width, screen.pixels_rgba().pixels().collect()));
+        }
+        screens.push(frames);
+    }
+    let expected_delays: Vec<_> = screens[0].iter().map(|f| f.0).collect();
+    assert_eq!(expected_delays, [10, 10, 10, 10]);
+    // identical frames are merged, and the change is only a single pixel
+    assert_eq!(screens[1].iter().map(|f| (f.0, f.1)).collect::<Vec<_>>(), [(20, 8), (20, 1)]);
+    assert_eq!(screens[0][1].2, screens[1][0].2);
+    assert_eq!(screens[0][3].2, screens[1][1].2);
+}
+
+#[test]
+fn optimize_keeps_interlacing() {
+    let pal = [0, 0, 0, 255, 255, 255];
+    let mut gif = Vec::new();
+    {
+        let mut enc = gif::Encoder::new(&mut gif, 4, 8, &pal).unwrap();
+        let buffer: Vec<u8> = (0..32).map(|i| u8::from(i / 4 % 3 == 0)).collect();
+        let mut frame = gif::Frame { width: 4, height: 8, interlaced: true, buffer: crate::encoderust::interlace_rows(&buffer, 4).into(), ..gif::Frame::default() };
+        frame.make_lzw_pre_encoded();
+        enc.write_lzw_pre_encoded_frame(&frame).unwrap();
+    }
+
+    let mut out = Vec::new();
+    optimize_gif(&gif[..], &mut out, None).unwrap();
+
+    let mut pixels = Vec::new();
+    for data in [&gif, &out] {
+        let mut gif_opts = gif::DecodeOptions::new();
+        gif_opts.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = gif_opts.read_info(&data[..]).unwrap();
+        assert!(decoder.next_frame_info().unwrap().unwrap().interlaced);
+        let mut buffer = vec![0; decoder.buffer_size()];
+        decoder.read_into_buffer(&mut buffer).unwrap();
+        pixels.push(buffer);
+    }
+    assert_eq!(pixels[0], pixels[1]);
+}
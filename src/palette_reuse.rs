@@ -0,0 +1,79 @@
+//! Checks whether a frame can be remapped to the palette of a previous frame, instead of being quantized again
+
+use imgref::ImgRef;
+use rgb::RGBA8;
+use std::collections::HashSet;
+
+/// At most this many of 1000 encoded pixels may have colors that the palette hasn't been made for
+const MAX_UNCOVERED_PERMILLE: usize = 5;
+
+/// Largest difference of a channel between a pixel and its palette color, when the palette is reused without dithering
+const MAX_CHANNEL_ERROR: u8 = 1;
+
+/// Which colors (reduced to 4 bits per channel) the palette has been made for
+pub(crate) struct ColorBins {
+    bins: Vec<bool>,
+}
+
+impl ColorBins {
+    /// Colors of opaque pixels that are going to be quantized. With `changed_only`, pixels unchanged according to the importance map are skipped.
+    pub fn new(image: ImgRef<RGBA8>, importance_map: &[u8], changed_only: bool) -> Self {
+        let mut bins = vec![false; 1 << 12];
+        for_each_encoded(image, importance_map, changed_only, |bin| bins[bin] = true);
+        Self { bins }
+    }
+
+    /// Nearly all encoded pixels of the image are in the bins
+    pub fn covers(&self, image: ImgRef<RGBA8>, importance_map: &[u8], changed_only: bool) -> bool {
+        let mut total = 0;
+        let mut uncovered = 0;
+        for_each_encoded(image, importance_map, changed_only, |bin| {
+            total += 1;
+            if !self.bins[bin] {
+                uncovered += 1;
+            }
+        });
+        uncovered * 1000 <= total * MAX_UNCOVERED_PERMILLE
+    }
+}
+
+/// The palette has been made for similar colors, but it could have dropped some rare ones.
+/// Transparent pixels are going to show the background.
+pub(crate) fn has_close_colors(image: ImgRef<RGBA8>, pal: &[RGBA8]) -> bool {
+    let mut checked = HashSet::new();
+    image.pixels().filter(|px| px.a >= 128).all(|px| {
+        !checked.insert(px) || pal.iter().any(|c| {
+            c.a > 0 && c.r.abs_diff(px.r) <= MAX_CHANNEL_ERROR && c.g.abs_diff(px.g) <= MAX_CHANNEL_ERROR && c.b.abs_diff(px.b) <= MAX_CHANNEL_ERROR
+        })
+    })
+}
+
+fn for_each_encoded(image: ImgRef<RGBA8>, importance_map: &[u8], changed_only: bool, mut cb: impl FnMut(usize)) {
+    for (px, &imp) in image.pixels().zip(importance_map) {
+        if px.a >= 128 && (!changed_only || imp > 0) {
+            cb((usize::from(px.r >> 4) << 8) | (usize::from(px.g >> 4) << 4) | usize::from(px.b >> 4));
+        }
+    }
+}
+
+#[test]
+fn coverage() {
+    let red = RGBA8::new(255, 0, 0, 255);
+    let blue = RGBA8::new(0, 0, 255, 255);
+    let mut pixels = vec![red; 1000];
+    let owner = ColorBins::new(ImgRef::new(&pixels, 1000, 1), &[1; 1000], false);
+    pixels[0] = RGBA8::new(250, 5, 3, 255);
+    assert!(owner.covers(ImgRef::new(&pixels, 1000, 1), &[1; 1000], false));
+    pixels[1..20].fill(blue);
+    assert!(!owner.covers(ImgRef::new(&pixels, 1000, 1), &[1; 1000], false));
+    // blue pixels haven't changed, so they're not going to be encoded
+    let mut importance_map = [1; 1000];
+    importance_map[1..20].fill(0);
+    assert!(owner.covers(ImgRef::new(&pixels, 1000, 1), &importance_map, true));
+
+    let pal = [RGBA8::new(0, 0, 0, 0), red];
+    assert!(!has_close_colors(ImgRef::new(&pixels, 1000, 1), &pal));
+    pixels[0] = RGBA8::new(254, 1, 0, 255);
+    pixels[1..20].fill(RGBA8::new(0, 0, 255, 0));
+    assert!(has_close_colors(ImgRef::new(&pixels, 1000, 1), &pal));
+}
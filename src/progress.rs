@@ -22,11 +22,32 @@ pub trait ProgressReporter: Send {
     #[cold]
     fn error(&mut self, _message: String) {}
 
+    /// Summary of how frame timestamps have been converted to delays. Called once, after all frames have been written.
+    ///
+    /// See [`TimingMode`][crate::TimingMode].
+    fn timing(&mut self, _report: &TimingReport) {}
+
     /// Not used :(
     /// Writing is done when `Writer::write()` call returns
     fn done(&mut self, _msg: &str) {}
 }
 
+/// Timing decisions made while writing frames
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct TimingReport {
+    /// Number of frames written
+    pub frames: usize,
+    /// Input frames that have been merged into other frames, because they were identical, similar (see [`Settings::merge_threshold`][crate::Settings::merge_threshold]), or exceeded the max frame rate
+    pub merged_frames: usize,
+    /// Frames which got a delay different from their input timestamps, due to rounding or minimum delay
+    pub adjusted_frames: usize,
+    /// Largest difference between a frame's end timestamp and its end in the file, in seconds
+    pub max_drift: f64,
+    /// Difference between length of the input and the file, in seconds. Positive if the file is longer.
+    pub final_drift: f64,
+}
+
 /// No-op progress reporter
 pub struct NoProgress {}
 
@@ -0,0 +1,129 @@
+//! Resizing of frames, optionally in linear light
+
+use crate::error::CatResult;
+use crate::{PushInCapacity, Resample, Settings};
+use imgref::{ImgRef, ImgVec};
+use loop9::loop9_img;
+use rgb::{RGBA, RGBA8};
+
+impl Resample {
+    fn filter(self) -> resize::Type {
+        match self {
+            Self::Point => resize::Type::Point,
+            Self::Triangle => resize::Type::Triangle,
+            Self::CatmullRom => resize::Type::Catrom,
+            Self::Mitchell => resize::Type::Mitchell,
+            Self::Lanczos3 => resize::Type::Lanczos3,
+        }
+    }
+}
+
+#[inline(never)]
+pub(crate) fn resample(src: ImgRef<RGBA8>, width: usize, height: usize, settings: &Settings) -> CatResult<ImgVec<RGBA8>> {
+    let (buf, src_width, src_height) = src.to_contiguous_buf();
+    let filter = settings.resample.filter();
+
+    if !settings.linear_light && settings.unsharp <= 0. {
+        let mut r = resize::new(src_width, src_height, width, height, resize::Pixel::RGBA8P, filter)?;
+        let mut dst = vec![RGBA8::new(0, 0, 0, 0); width * height];
+        r.resize(&buf, &mut dst)?;
+        return Ok(ImgVec::new(dst, width, height));
+    }
+
+    // 16 bits are needed to keep precision of dark colors in linear light
+    let gamma = Gamma::new(settings.linear_light);
+    let src16: Vec<_> = buf.iter().map(|px| gamma.to_linear(*px)).collect();
+    let mut r = resize::new(src_width, src_height, width, height, resize::Pixel::RGBA16P, filter)?;
+    let mut dst = vec![RGBA::new(0, 0, 0, 0); width * height];
+    r.resize(&src16, &mut dst)?;
+
+    let dst = if settings.unsharp > 0. {
+        unsharp(ImgRef::new(&dst, width, height), settings.unsharp)
+    } else {
+        dst
+    };
+    Ok(ImgVec::new(dst.into_iter().map(|px| gamma.to_srgb(px)).collect(), width, height))
+}
+
+/// Conversion between 8-bit sRGB and 16-bit linear (or just widened) values. Alpha is always linear.
+struct Gamma {
+    linear: [u16; 256],
+    /// Linear values halfway between consecutive sRGB values, for rounding back to the nearest one
+    thresholds: [u16; 255],
+}
+
+impl Gamma {
+    fn new(linear_light: bool) -> Self {
+        let to_linear = |srgb: f32| -> f32 {
+            if !linear_light {
+                srgb
+            } else if srgb <= 0.04045 {
+                srgb / 12.92
+            } else {
+                ((srgb + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let mut linear = [0; 256];
+        for (i, l) in linear.iter_mut().enumerate() {
+            *l = (to_linear(i as f32 / 255.) * 65535. + 0.5) as u16;
+        }
+        let mut thresholds = [0; 255];
+        for (i, t) in thresholds.iter_mut().enumerate() {
+            *t = (to_linear((i as f32 + 0.5) / 255.) * 65535. + 0.5) as u16;
+        }
+        Self { linear, thresholds }
+    }
+
+    #[inline]
+    fn to_linear(&self, px: RGBA8) -> RGBA<u16> {
+        RGBA::new(self.linear[px.r as usize], self.linear[px.g as usize], self.linear[px.b as usize], u16::from(px.a) * 257)
+    }
+
+    #[inline]
+    fn to_srgb(&self, px: RGBA<u16>) -> RGBA8 {
+        let srgb = |v: u16| self.thresholds.partition_point(|&t| t <= v) as u8;
+        RGBA8::new(srgb(px.r), srgb(px.g), srgb(px.b), ((u32::from(px.a) + 128) / 257) as u8)
+    }
+}
+
+/// Restores some of the contrast of edges lost in downscaling. `amount` is how much of the difference from a blurred image is added.
+fn unsharp(img: ImgRef<RGBA<u16>>, amount: f32) -> Vec<RGBA<u16>> {
+    let mut out = Vec::with_capacity(img.width() * img.height());
+    loop9_img(img, |_, _, top, mid, bot| {
+        out.push_in_cap(if mid.curr.a > 0 {
+            let sharpen = |get: fn(RGBA<u16>) -> u16| {
+                let px = |p| f32::from(get(p));
+                // 3×3 Gaussian
+                let blurred = (px(top.prev) + px(top.next) + px(bot.prev) + px(bot.next)
+                    + 2. * (px(top.curr) + px(mid.prev) + px(mid.next) + px(bot.curr))
+                    + 4. * px(mid.curr)) / 16.;
+                (px(mid.curr) + amount * (px(mid.curr) - blurred)).round().clamp(0., 65535.) as u16
+            };
+            RGBA::new(sharpen(|p| p.r), sharpen(|p| p.g), sharpen(|p| p.b), mid.curr.a)
+        } else {
+            mid.curr
+        });
+    });
+    out
+}
+
+#[test]
+fn gamma_roundtrip() {
+    for linear_light in [false, true] {
+        let gamma = Gamma::new(linear_light);
+        for v in 0..=255 {
+            let px = RGBA8::new(v, 255 - v, v / 2, v);
+            assert_eq!(px, gamma.to_srgb(gamma.to_linear(px)));
+        }
+    }
+    // average of black and white is much brighter than 128 in linear light
+    let gamma = Gamma::new(true);
+    let mid = (u32::from(gamma.to_linear(RGBA8::new(0, 0, 0, 255)).r) + u32::from(gamma.to_linear(RGBA8::new(255, 255, 255, 255)).r)) / 2;
+    assert!(gamma.to_srgb(RGBA::new(mid as u16, 0, 0, 65535)).r > 180);
+}
+
+#[test]
+fn unsharp_flat_is_unchanged() {
+    let flat = vec![RGBA::new(1000, 20000, 65535, 65535); 5 * 5];
+    assert_eq!(flat, unsharp(ImgRef::new(&flat, 5, 5), 1.));
+}
@@ -0,0 +1,129 @@
+//! Push-style encoding without managing threads
+//!
+//! [`StreamingEncoder`] runs the [`Writer`][crate::Writer] on its own thread,
+//! and hands back the GIF data as it's being written.
+
+use crate::collector::{Collector, ImgVec, RGBA8};
+use crate::error::GifResult;
+use crate::progress::NoProgress;
+use crate::{Error, Settings};
+use crossbeam_channel::{Receiver, Sender};
+use std::io;
+use std::thread::{self, JoinHandle};
+
+/// Encoder that takes frames one by one, and gives back the file in chunks
+///
+/// Unlike the [`Collector`]/[`Writer`][crate::Writer] pair, this doesn't need a thread from the caller.
+/// Frames are compressed in the background, and [`push_frame`][Self::push_frame] only blocks briefly
+/// when the encoder can't keep up.
+///
+/// ```rust,no_run
+/// # fn get_frames() -> Vec<(gifski::collector::ImgVec<gifski::collector::RGBA8>, f64)> { vec![] }
+/// let mut encoder = gifski::StreamingEncoder::new(gifski::Settings::default())?;
+/// let mut gif = Vec::new();
+/// for (image, pts) in get_frames() {
+///     encoder.push_frame(image, pts)?;
+///     encoder.poll_output(&mut gif);
+/// }
+/// gif.extend(encoder.finish()?);
+/// # Ok::<_, gifski::Error>(())
+/// ```
+pub struct StreamingEncoder {
+    collector: Option<Collector>,
+    next_frame_index: usize,
+    output: Receiver<Vec<u8>>,
+    write_thread: Option<JoinHandle<GifResult<()>>>,
+}
+
+/// Sends everything written to it to the `StreamingEncoder`
+struct ChunkSender(Sender<Vec<u8>>);
+
+impl io::Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.0.send(buf.to_vec()).map_err(|_| io::ErrorKind::BrokenPipe)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl StreamingEncoder {
+    /// Starts the encoder's threads. The settings are validated the same way as in [`new()`][crate::new].
+    pub fn new(settings: Settings) -> GifResult<Self> {
+        let (collector, writer) = crate::new(settings)?;
+        let (output_send, output) = crossbeam_channel::unbounded();
+        let write_thread = thread::Builder::new().name("stream".into()).spawn(move || {
+            writer.write(ChunkSender(output_send), &mut NoProgress {})
+        })?;
+        Ok(Self {
+            collector: Some(collector),
+            next_frame_index: 0,
+            output,
+            write_thread: Some(write_thread),
+        })
+    }
+
+    /// Adds the next frame of the animation.
+    ///
+    /// Presentation timestamp is time in seconds (since file start at 0) when this frame is to be displayed.
+    /// Frames must be pushed in order.
+    ///
+    /// If encoding has failed, this returns the encoder's error.
+    pub fn push_frame(&mut self, image: ImgVec<RGBA8>, presentation_timestamp: f64) -> GifResult<()> {
+        let collector = self.collector.as_ref().ok_or(Error::Aborted)?;
+        let res = collector.add_frame_rgba(self.next_frame_index, image, presentation_timestamp);
+        self.pushed(res)
+    }
+
+    /// Adds the next frame of the animation, displayed for `duration` seconds after the previous frame.
+    ///
+    /// See [`Collector::add_frame_rgba_with_duration`]. Don't mix it with [`push_frame`][Self::push_frame].
+    pub fn push_frame_with_duration(&mut self, image: ImgVec<RGBA8>, duration: f64) -> GifResult<()> {
+        let collector = self.collector.as_ref().ok_or(Error::Aborted)?;
+        let res = collector.add_frame_rgba_with_duration(self.next_frame_index, image, duration);
+        self.pushed(res)
+    }
+
+    fn pushed(&mut self, res: GifResult<()>) -> GifResult<()> {
+        if let Err(err) = res {
+            // the send error only means the writer has stopped, and the writer knows why
+            self.collector = None;
+            self.join_writer()?;
+            return Err(err);
+        }
+        self.next_frame_index += 1;
+        Ok(())
+    }
+
+    /// Appends GIF data that has been written so far to `buf`. Doesn't block.
+    ///
+    /// Returns number of bytes appended. The file is complete only after [`finish`][Self::finish].
+    pub fn poll_output(&mut self, buf: &mut Vec<u8>) -> usize {
+        let start_len = buf.len();
+        for chunk in self.output.try_iter() {
+            buf.extend_from_slice(&chunk);
+        }
+        buf.len() - start_len
+    }
+
+    /// Waits until all frames are encoded, and returns the rest of the file that hasn't been polled yet.
+    pub fn finish(mut self) -> GifResult<Vec<u8>> {
+        // closes the queue, so that the writer knows it's the last frame
+        self.collector = None;
+        self.join_writer()?;
+        let mut rest = Vec::new();
+        self.poll_output(&mut rest);
+        Ok(rest)
+    }
+
+    fn join_writer(&mut self) -> GifResult<()> {
+        match self.write_thread.take() {
+            Some(thread) => thread.join().map_err(crate::handle_join_error)?,
+            None => Err(Error::Aborted),
+        }
+    }
+}
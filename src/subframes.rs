@@ -0,0 +1,162 @@
+//! Splitting of a frame's changed area into several GIF images, when changes are far apart
+
+use crate::is_matching_pixel;
+use gif::DisposalMethod;
+use imgref::{ImgRef, ImgVec};
+use rgb::{RGB8, RGBA8};
+
+/// Unchanged rows or columns between areas have to be at least this wide to try to split there
+const MIN_GAP: usize = 8;
+/// Every image needs its own descriptor, so too many small ones would be wasteful anyway
+const MAX_RECTS: usize = 4;
+/// Graphic control extension and image descriptor
+const IMAGE_OVERHEAD: usize = 8 + 10;
+
+/// Area of a frame: left, top, width, height
+pub(crate) type Rect = (u16, u16, usize, usize);
+/// Same as `Rect`, but within the changed area
+type Area = (usize, usize, usize, usize);
+
+/// Returns rectangles that cover all changes in `bbox`, if writing them as separate images is smaller than writing the whole `bbox`.
+///
+/// Only for frames with `Keep` disposal, because images without delay are disposed before the next one is drawn.
+pub(crate) fn split_changed_area(image8: ImgRef<u8>, image8_pal: &[RGB8], transparent_index: Option<u8>, screen: ImgRef<RGBA8>, bbox: Rect) -> Option<Vec<Rect>> {
+    let (left, top, width, height) = bbox;
+    let (left, top) = (usize::from(left), usize::from(top));
+    if width < MIN_GAP * 2 && height < MIN_GAP * 2 {
+        return None;
+    }
+    let changed = ImgVec::new(
+        image8.sub_image(left, top, width, height).pixels().zip(screen.sub_image(left, top, width, height).pixels())
+            .map(|(px, bg)| !is_matching_pixel(px, bg, image8_pal, transparent_index, DisposalMethod::Keep))
+            .collect(),
+        width, height);
+
+    let rects = split(changed.as_ref(), bounding_box(changed.as_ref(), (0, 0, width, height))?);
+    if rects.len() < 2 {
+        return None;
+    }
+
+    // it's only worth it if LZW compresses the parts better than the gaps between them.
+    // This runs for every frame in the serial remapping stage, so the sizes are only estimated.
+    let palette_size = 3 * image8_pal.len().max(2).next_power_of_two();
+    let whole_size = estimated_lzw_size(image8.sub_image(left, top, width, height));
+    let parts_size: usize = rects.iter().map(|&(l, t, w, h)| {
+        palette_size + IMAGE_OVERHEAD + estimated_lzw_size(image8.sub_image(left + l, top + t, w, h))
+    }).sum();
+    if parts_size >= whole_size + palette_size + IMAGE_OVERHEAD {
+        return None;
+    }
+    Some(rects.into_iter().map(|(l, t, w, h)| ((left + l) as u16, (top + t) as u16, w, h)).collect())
+}
+
+/// Starts with `rect` tight around the changes, and keeps splitting the rectangle that has the widest gap
+fn split(changed: ImgRef<bool>, rect: Area) -> Vec<Area> {
+    let mut out = vec![rect];
+    while out.len() < MAX_RECTS {
+        let Some((index, (first, second))) = out.iter().enumerate()
+            .filter_map(|(i, &rect)| Some((i, split_at_widest_gap(changed, rect)?)))
+            .max_by_key(|&(_, (_, gap))| gap)
+            .map(|(i, (halves, _))| (i, halves)) else {
+            break;
+        };
+        // halves of a tight rect always have some changes
+        let (Some(first), Some(second)) = (bounding_box(changed, first), bounding_box(changed, second)) else {
+            break;
+        };
+        out[index] = first;
+        out.push(second);
+    }
+    out
+}
+
+/// Two halves on both sides of the widest gap, and the width of the gap
+fn split_at_widest_gap(changed: ImgRef<bool>, (left, top, width, height): Area) -> Option<((Area, Area), usize)> {
+    let area = changed.sub_image(left, top, width, height);
+    let empty_rows: Vec<bool> = area.rows().map(|row| !row.iter().any(|&c| c)).collect();
+    let empty_columns: Vec<bool> = (0..width).map(|x| !area.rows().any(|row| row[x])).collect();
+    let (row_start, row_gap) = widest_gap(&empty_rows);
+    let (column_start, column_gap) = widest_gap(&empty_columns);
+    if row_gap >= column_gap && row_gap >= MIN_GAP {
+        Some((((left, top, width, row_start), (left, top + row_start + row_gap, width, height - row_start - row_gap)), row_gap))
+    } else if column_gap >= MIN_GAP {
+        Some((((left, top, column_start, height), (left + column_start + column_gap, top, width - column_start - column_gap, height)), column_gap))
+    } else {
+        None
+    }
+}
+
+/// Start and length of the longest run of `true`
+fn widest_gap(empty: &[bool]) -> (usize, usize) {
+    let mut best = (0, 0);
+    let mut start = 0;
+    for (i, &is_empty) in empty.iter().enumerate() {
+        if !is_empty {
+            start = i + 1;
+        } else if i + 1 - start > best.1 {
+            best = (start, i + 1 - start);
+        }
+    }
+    best
+}
+
+fn bounding_box(changed: ImgRef<bool>, (left, top, width, height): Area) -> Option<Area> {
+    let area = changed.sub_image(left, top, width, height);
+    let rows: Vec<usize> = area.rows().enumerate().filter(|(_, row)| row.iter().any(|&c| c)).map(|(y, _)| y).collect();
+    let first_row = *rows.first()?;
+    let last_row = *rows.last()?;
+    let first_column = (0..width).find(|&x| area.rows().any(|row| row[x]))?;
+    let last_column = (0..width).rev().find(|&x| area.rows().any(|row| row[x]))?;
+    Some((left + first_column, top + first_row, last_column + 1 - first_column, last_row + 1 - first_row))
+}
+
+/// LZW learns longer and longer strings of a repeated color, so a run of `n` pixels takes about `sqrt(2n)` codes.
+/// Codes are up to 12 bits long. It's rough, but it doesn't need to compress anything.
+fn estimated_lzw_size(image: ImgRef<u8>) -> usize {
+    let mut pixels = image.pixels();
+    let Some(mut prev) = pixels.next() else {
+        return 0;
+    };
+    let mut run = 1;
+    let mut codes = 0.;
+    for px in pixels {
+        if px == prev {
+            run += 1;
+        } else {
+            codes += (2. * run as f32).sqrt();
+            prev = px;
+            run = 1;
+        }
+    }
+    codes += (2. * run as f32).sqrt();
+    let bytes = (codes * 12. / 8.) as usize;
+    // data is in sub-blocks of up to 255 bytes
+    bytes + bytes / 255 + 2
+}
+
+#[test]
+fn far_apart_changes() {
+    let pal = [RGB8::new(0, 0, 0), RGB8::new(255, 255, 255), RGB8::new(0, 255, 0)];
+    let screen = ImgVec::new(vec![RGBA8::new(255, 255, 255, 255); 200 * 120], 200, 120);
+    // white on white is unchanged
+    let mut image8 = ImgVec::new(vec![1; 200 * 120], 200, 120);
+    for (x, y) in [(2_usize, 3_usize), (4, 5), (195, 110), (197, 117)] {
+        image8[(x, y)] = 0;
+    }
+    let bbox = (2, 3, 196, 115);
+    let rects = split_changed_area(image8.as_ref(), &pal, None, screen.as_ref(), bbox).unwrap();
+    assert_eq!(rects, [(2, 3, 3, 3), (195, 110, 3, 8)]);
+
+    // a change in-between gets its own rectangle
+    image8[(100_usize, 60_usize)] = 2;
+    let rects = split_changed_area(image8.as_ref(), &pal, None, screen.as_ref(), bbox).unwrap();
+    assert_eq!(rects.len(), 3);
+
+    // a flat area is much cheaper than noise
+    let flat = ImgVec::new(vec![7; 64 * 64], 64, 64);
+    let noise = ImgVec::new((0..64 * 64).map(|i: usize| (i * 7919 % 251) as u8).collect(), 64, 64);
+    assert!(estimated_lzw_size(flat.as_ref()) * 20 < estimated_lzw_size(noise.as_ref()));
+
+    assert_eq!((3, 4), widest_gap(&[false, true, false, true, true, true, true, false]));
+    assert!(split_changed_area(image8.as_ref(), &pal, None, screen.as_ref(), (2, 3, 3, 3)).is_none());
+}
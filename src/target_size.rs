@@ -0,0 +1,235 @@
+//! Re-encoding with lower settings until the file fits in `Settings::max_size`
+
+use crate::collector::{FrameSource, InputFrame};
+use crate::error::CatResult;
+use crate::progress::{ProgressReporter, TimingReport};
+use crate::{crop, handle_join_error, Crop, Error, Resize, Writer};
+use crossbeam_channel::Receiver;
+use std::io::Write;
+use std::thread;
+
+/// Each attempt encodes the whole animation, so don't try forever
+const MAX_ATTEMPTS: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Attempt {
+    quality: u8,
+    lossy_quality: u8,
+    /// Relative to the size the settings would normally give
+    scale: f32,
+}
+
+impl Attempt {
+    /// Settings that are expected to make the file smaller by `ratio` (< 1)
+    fn smaller(self, ratio: f64) -> Option<Self> {
+        let mut next = self;
+        // when it's only a bit too large, noise and banding are less noticeable than losing resolution
+        let can_be_lossier = cfg!(feature = "gifsicle") && next.lossy_quality > 40;
+        if ratio > 0.6 && (can_be_lossier || next.quality > 60) {
+            if can_be_lossier {
+                next.lossy_quality = next.lossy_quality.saturating_sub(25).max(40);
+            }
+            next.quality = next.quality.saturating_sub(15).max(60);
+            return Some(next);
+        }
+        // edges and frames' overhead don't shrink with the area, so the size falls slower than the number of pixels
+        next.scale = self.scale * (ratio * 0.9) as f32;
+        // resampling blends colors, and palettes with more colors than needed make the file larger
+        next.quality = next.quality.saturating_sub(15).max(30);
+        if next.scale < 1. / 32. {
+            return None;
+        }
+        Some(next)
+    }
+}
+
+/// Stops writing as soon as the file gets too large.
+/// The user's reporter gets byte counts only from the first attempt, and each frame only once across all attempts.
+struct SizeLimitReporter<'a> {
+    inner: &'a mut dyn ProgressReporter,
+    forward: bool,
+    /// By all attempts so far, so that the progress doesn't go backwards
+    reported_frames: u64,
+    max_size: u64,
+    total_frames: u64,
+    frames_done: u64,
+    written: u64,
+    /// Including the header. The first frame is usually the largest, so it's not counted in the average.
+    first_frame_size: u64,
+    user_aborted: bool,
+    /// Reported only for the attempt that is kept
+    timing: Option<TimingReport>,
+}
+
+impl SizeLimitReporter<'_> {
+    /// Extrapolated from the frames written so far
+    fn estimated_size(&self) -> u64 {
+        if self.frames_done <= 1 || self.frames_done >= self.total_frames {
+            return self.written;
+        }
+        let rest = self.written.saturating_sub(self.first_frame_size);
+        self.first_frame_size + rest * (self.total_frames - 1) / (self.frames_done - 1)
+    }
+}
+
+impl ProgressReporter for SizeLimitReporter<'_> {
+    fn increase(&mut self) -> bool {
+        self.frames_done += 1;
+        if self.frames_done == 1 {
+            self.first_frame_size = self.written;
+        }
+        if self.frames_done > self.reported_frames {
+            self.reported_frames = self.frames_done;
+            if !self.inner.increase() {
+                self.user_aborted = true;
+                return false;
+            }
+        }
+        // no point finishing a file that is already too large
+        self.written <= self.max_size
+    }
+
+    fn written_bytes(&mut self, current_file_size_in_bytes: u64) {
+        self.written = current_file_size_in_bytes;
+        if self.forward {
+            self.inner.written_bytes(current_file_size_in_bytes);
+        }
+    }
+
+    fn timing(&mut self, report: &TimingReport) {
+        self.timing = Some(report.clone());
+    }
+
+    fn error(&mut self, message: String) {
+        // every attempt would report the same errors
+        if self.forward {
+            self.inner.error(message);
+        }
+    }
+}
+
+impl Writer {
+    /// Buffers all frames, and encodes them until the result is smaller than `max_size`
+    #[inline(never)]
+    pub(crate) fn write_with_max_size(&self, inputs: Receiver<InputFrame>, max_size: u64, writer: &mut dyn Write, reporter: &mut dyn ProgressReporter) -> CatResult<()> {
+        // frames are going to be sent again for every attempt, so decode PNGs only once
+        let frames = inputs.into_iter().map(|f| Ok(InputFrame {
+            frame: FrameSource::Pixels(f.frame.into_pixels()?),
+            ..f
+        })).collect::<CatResult<Vec<_>>>()?;
+
+        let Some(FrameSource::Pixels(first_frame)) = frames.first().map(|f| &f.frame) else {
+            return Err(Error::NoFrames);
+        };
+        // every attempt would find the same borders
+        let crop = if self.settings.crop == Crop::Auto {
+            let sample: Vec<_> = frames.iter().take(crop::AUTO_CROP_SAMPLE_FRAMES).filter_map(|f| match &f.frame {
+                FrameSource::Pixels(image) => Some(image.as_ref()),
+                _ => None,
+            }).collect();
+            crop::detect_borders(&sample)
+        } else {
+            self.settings.crop
+        };
+        let (_, _, width, height) = crop.area(first_frame.width(), first_frame.height())?;
+        let (width, height) = self.settings.dimensions_for_image(width, height);
+
+        let mut attempt = Attempt {
+            quality: self.settings.quality,
+            lossy_quality: self.settings.lossy_quality(),
+            scale: 1.,
+        };
+        let mut out = Vec::new();
+        let mut reported_frames = 0;
+        for n in 0..MAX_ATTEMPTS {
+            let mut settings = self.settings;
+            settings.crop = crop;
+            settings.quality = attempt.quality;
+            settings.motion_quality = Some(self.settings.motion_quality().min(attempt.quality));
+            settings.lossy_quality = Some(attempt.lossy_quality);
+            if attempt.scale < 1. {
+                let width = ((width as f32 * attempt.scale) as u32).max(1);
+                let height = ((height as f32 * attempt.scale) as u32).max(1);
+                // keeps the crop of Fill and the aspect ratio of Exact
+                settings.resize = match self.settings.resize {
+                    Resize::Fill { .. } => Resize::Fill { width, height },
+                    Resize::Exact { .. } => Resize::Exact { width, height },
+                    Resize::Stretch { .. } => Resize::Stretch { width, height },
+                    _ => Resize::Fit { width: Some(width), height: Some(height) },
+                };
+            }
+            let attempt_writer = Writer {
+                queue_iter: None,
+                settings,
+                max_threads: self.max_threads,
+                fixed_colors: self.fixed_colors.clone(),
+            };
+
+            let mut limit = SizeLimitReporter {
+                inner: &mut *reporter,
+                forward: n == 0,
+                reported_frames,
+                max_size,
+                total_frames: frames.len() as u64,
+                frames_done: 0,
+                written: 0,
+                first_frame_size: 0,
+                user_aborted: false,
+                timing: None,
+            };
+
+            out.clear();
+            let res = thread::scope(|s| {
+                let (queue, queue_iter) = crossbeam_channel::bounded(4);
+                let frames = &frames;
+                let feed_thread = thread::Builder::new().name("replay".into()).spawn_scoped(s, move || {
+                    for f in frames {
+                        // the writer has given up
+                        if queue.send(f.clone()).is_err() {
+                            break;
+                        }
+                    }
+                })?;
+                let res = attempt_writer.write_inner(queue_iter, &mut out, &mut limit);
+                feed_thread.join().map_err(handle_join_error)?;
+                res
+            });
+
+            reported_frames = limit.reported_frames;
+            let timing = limit.timing.take();
+            match res {
+                Ok(()) if out.len() as u64 <= max_size => {
+                    if n > 0 {
+                        reporter.written_bytes(out.len() as u64);
+                    }
+                    if let Some(timing) = &timing {
+                        reporter.timing(timing);
+                    }
+                    writer.write_all(&out)?;
+                    return Ok(());
+                },
+                Ok(()) | Err(Error::Aborted) if !limit.user_aborted => {},
+                Err(err) => return Err(err),
+                Ok(()) => return Err(Error::Aborted),
+            }
+
+            let estimated_size = limit.estimated_size().max(out.len() as u64).max(1);
+            attempt = attempt.smaller(max_size as f64 / estimated_size as f64).ok_or(Error::TooLarge)?;
+        }
+        Err(Error::TooLarge)
+    }
+}
+
+#[test]
+fn attempts_get_smaller() {
+    let mut a = Attempt { quality: 100, lossy_quality: 100, scale: 1. };
+    let mut n = 0;
+    while let Some(next) = a.smaller(0.8) {
+        assert!(next.quality <= a.quality && next.lossy_quality <= a.lossy_quality && next.scale <= a.scale);
+        assert_ne!(next, a);
+        a = next;
+        n += 1;
+    }
+    assert!(n > 2);
+    assert!(a.scale < 0.1);
+}
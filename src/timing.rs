@@ -0,0 +1,133 @@
+//! Conversion of presentation timestamps to delays of the output format
+
+use crate::error::CatResult;
+use crate::progress::TimingReport;
+use crate::{Error, TimingMode};
+
+/// A frame is idle if fewer than 1/this of its pixels have changed, e.g. only a blinking cursor
+const IDLE_CHANGED_AREA_DIVISOR: usize = 500;
+
+/// Rounds frame delays, keeping track of the rounding error so that it doesn't accumulate
+pub(crate) struct DelayRounding {
+    mode: TimingMode,
+    /// 100 for GIF's centiseconds
+    units_per_second: u32,
+    min_delay: u64,
+    max_delay: u64,
+    pts_in_units: u64,
+    last_ordinal_frame_number: usize,
+    report: TimingReport,
+}
+
+impl DelayRounding {
+    pub fn new(mode: TimingMode, units_per_second: u32, min_delay: u64, max_delay: u64) -> Self {
+        Self {
+            mode,
+            units_per_second,
+            min_delay,
+            max_delay,
+            pts_in_units: 0,
+            last_ordinal_frame_number: 0,
+            report: TimingReport::default(),
+        }
+    }
+
+    /// Delay of a frame that is supposed to end at `end_pts` seconds
+    pub fn delay(&mut self, end_pts: f64, ordinal_frame_number: usize) -> CatResult<u64> {
+        let units = f64::from(self.units_per_second);
+        let end = end_pts * units;
+        let wanted = (end.round() as u64).saturating_sub(self.pts_in_units);
+        let delay = wanted.clamp(self.min_delay, self.max_delay);
+        // timestamps have been divided by units_per_second, so they may be a bit off
+        let is_exact = (end - end.round()).abs() < 0.01;
+        if delay != wanted || !is_exact {
+            if self.mode == TimingMode::Strict {
+                return Err(Error::Timing(if is_exact {
+                    format!("Frame {ordinal_frame_number} would need a delay of {wanted}/{units}s, but it must be {}-{}", self.min_delay, self.max_delay)
+                } else {
+                    format!("Frame {ordinal_frame_number} ends at {end_pts:.4}s, which is not a multiple of 1/{units}s")
+                }));
+            }
+            self.report.adjusted_frames += 1;
+        }
+        self.pts_in_units += delay;
+
+        let drift = self.pts_in_units as f64 / units - end_pts;
+        self.report.max_drift = self.report.max_drift.max(drift.abs());
+        self.report.final_drift = drift;
+        self.report.merged_frames += ordinal_frame_number.saturating_sub(self.last_ordinal_frame_number + 1);
+        self.last_ordinal_frame_number = ordinal_frame_number;
+        self.report.frames += 1;
+        Ok(delay)
+    }
+
+    pub fn report(&self) -> &TimingReport {
+        &self.report
+    }
+}
+
+/// Shortens stretches of time in which nothing much changes on screen, by shifting timestamps of all frames after them
+pub(crate) struct IdleLimit {
+    /// In seconds
+    max_idle: f64,
+    /// Start of the last frame that wasn't idle
+    active_pts: Option<f64>,
+    /// Time cut out before the current stretch
+    stretch_shift: f64,
+}
+
+impl IdleLimit {
+    pub fn new(max_idle: f64) -> Self {
+        Self { max_idle, active_pts: None, stretch_shift: 0. }
+    }
+
+    /// Returns the new timestamp, and `true` if the frame starts after the stretch has been cut off.
+    /// Such frames have nowhere to be shown, so they need to be merged into the next frame.
+    pub fn retime(&mut self, pts: f64, importance_map: &[u8]) -> (f64, bool) {
+        let Some(active_pts) = self.active_pts else {
+            self.active_pts = Some(pts);
+            return (pts, false);
+        };
+        let shift = self.stretch_shift + (pts - active_pts - self.max_idle).max(0.);
+        let is_idle = importance_map.iter().filter(|&&i| i > 0).count() * IDLE_CHANGED_AREA_DIVISOR < importance_map.len();
+        if is_idle {
+            return (pts - shift, pts - active_pts >= self.max_idle);
+        }
+        self.active_pts = Some(pts);
+        self.stretch_shift = shift;
+        (pts - shift, false)
+    }
+
+    pub fn max_idle(&self) -> f64 {
+        self.max_idle
+    }
+}
+
+#[test]
+fn idle_limit() {
+    let active = [255; 100];
+    let mut cursor = [0; 1000];
+    cursor[0] = 100;
+    let mut idle = IdleLimit::new(1.);
+    assert_eq!((0., false), idle.retime(0., &active));
+    assert_eq!((0.5, false), idle.retime(0.5, &cursor));
+    assert_eq!((1., true), idle.retime(1.5, &cursor));
+    assert_eq!((1., true), idle.retime(3., &cursor));
+    assert_eq!((1., false), idle.retime(4., &active));
+    assert_eq!((1.5, false), idle.retime(4.5, &active));
+    assert_eq!((2.5, false), idle.retime(5.5, &active));
+}
+
+#[test]
+fn compensates_rounding() {
+    let mut timing = DelayRounding::new(TimingMode::Compensate, 100, 2, 30000);
+    let delays: Vec<_> = (1..=6).map(|n| timing.delay(f64::from(n) / 30., n as usize).unwrap()).collect();
+    assert_eq!(delays, [3, 4, 3, 3, 4, 3]);
+    assert!(timing.report().final_drift.abs() < 0.001);
+    assert_eq!(timing.report().adjusted_frames, 4);
+
+    let mut timing = DelayRounding::new(TimingMode::Strict, 100, 2, 30000);
+    assert_eq!(timing.delay(0.05, 1).unwrap(), 5);
+    assert!(timing.delay(0.06, 2).is_err());
+    assert!(timing.delay(0.1 + 1. / 30., 3).is_err());
+}
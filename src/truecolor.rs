@@ -0,0 +1,142 @@
+//! Frames taken straight from the denoiser, without quantization, for formats that have more than 256 colors and semi-transparent pixels
+
+use crate::error::CatResult;
+use crate::progress::ProgressReporter;
+use crate::{changed_bbox, combine_res, handle_join_error, minipool, ordqueue_new, DelayRounding, DiffMessage, Error, MergedFrames, OrdQueueIter, Settings, Writer};
+use crossbeam_channel::Receiver;
+use imgref::{ImgRef, ImgVec};
+use rgb::RGBA8;
+use std::io::Write;
+use std::sync::Mutex;
+use std::thread;
+
+/// Changed area of a frame, to be compressed
+struct TruecolorFrameMessage {
+    /// 0..
+    frame_index: usize,
+    /// 1..
+    ordinal_frame_number: usize,
+    end_pts: f64,
+    left: usize,
+    top: usize,
+    image: ImgVec<RGBA8>,
+}
+
+struct EncodedFrame<F> {
+    ordinal_frame_number: usize,
+    end_pts: f64,
+    frame: F,
+}
+
+/// Output file format backend for frames that aren't quantized. Frames are compressed in parallel, and then written in order.
+///
+/// Each frame replaces all pixels of its area, so semi-transparent pixels aren't blended with the previous frame.
+pub(crate) trait TruecolorEncoder<W: Write> {
+    type Frame: Send;
+    /// Delays are in `1/UNITS_PER_SECOND` of a second
+    const UNITS_PER_SECOND: u32;
+    const MIN_DELAY: u64;
+    const MAX_DELAY: u64;
+    /// WebP stores offsets divided by 2
+    const EVEN_OFFSETS: bool;
+
+    fn new(writer: W) -> Self;
+    fn compress_frame(left: usize, top: usize, image: ImgRef<RGBA8>, settings: &Settings) -> CatResult<Self::Frame>;
+    /// The first frame is the whole canvas
+    fn write_frame(&mut self, frame: Self::Frame, delay: u64, settings: &Settings) -> CatResult<()>;
+    /// Size the file is going to have. Frames are buffered until `finish()`, because headers need their number or size.
+    fn written_bytes(&self) -> u64;
+    fn finish(&mut self, settings: &Settings) -> CatResult<()>;
+}
+
+impl Writer {
+    /// Frames that replace only the area that changed since the previous frame
+    pub(crate) fn write_truecolor<'w, E: TruecolorEncoder<&'w mut dyn Write>>(&self, inputs: Receiver<DiffMessage>, writer: &'w mut dyn Write, reporter: &Mutex<Option<&mut dyn ProgressReporter>>) -> CatResult<()> {
+        let (encoded_queue, encoded_recv) = ordqueue_new(2);
+        thread::scope(|s| {
+            let encode_thread = thread::Builder::new().name("truecolor".into()).spawn_scoped(s, move || {
+                minipool::new_channel(self.max_threads.min(4.try_into()?), "truecolor", move |frames| {
+                    let mut prev_image: Option<ImgVec<RGBA8>> = None;
+                    let mut frame_index = 0;
+                    let mut merged = MergedFrames::new(self.settings.merge_threshold, inputs.into_iter());
+                    while let Some(frame) = merged.next() {
+                        let end_pts = merged.end_pts(&frame);
+                        let DiffMessage { image, ordinal_frame_number, .. } = frame;
+
+                        let (left, top, width, height) = prev_image.as_ref()
+                            .and_then(|prev| changed_bbox(prev.as_ref(), image.as_ref()))
+                            .map(|bbox| if E::EVEN_OFFSETS { align_to_even(bbox) } else { bbox })
+                            .unwrap_or((0, 0, image.width(), image.height()));
+                        let sub_image = image.sub_image(left, top, width, height);
+                        frames.send(TruecolorFrameMessage {
+                            frame_index,
+                            ordinal_frame_number,
+                            end_pts,
+                            left, top,
+                            image: ImgVec::new(sub_image.to_contiguous_buf().0.into_owned(), width, height),
+                        })?;
+                        frame_index += 1;
+                        prev_image = Some(image);
+                    }
+                    Ok(())
+                }, move |TruecolorFrameMessage { frame_index, ordinal_frame_number, end_pts, left, top, image }| {
+                    let frame = E::compress_frame(left, top, image.as_ref(), &self.settings)?;
+                    Ok(encoded_queue.send(frame_index, EncodedFrame { ordinal_frame_number, end_pts, frame })?)
+                })
+            })?;
+            let res0 = self.write_truecolor_file(E::new(writer), encoded_recv, reporter);
+            let res1 = encode_thread.join().map_err(handle_join_error)?;
+            combine_res(res0, res1)
+        })
+    }
+
+    fn write_truecolor_file<W: Write, E: TruecolorEncoder<W>>(&self, mut enc: E, encoded_frames: OrdQueueIter<EncodedFrame<E::Frame>>, reporter: &Mutex<Option<&mut dyn ProgressReporter>>) -> CatResult<()> {
+        let mut timing = DelayRounding::new(self.settings.timing, E::UNITS_PER_SECOND, E::MIN_DELAY, E::MAX_DELAY);
+        let mut n_done = 0;
+        for EncodedFrame { ordinal_frame_number, end_pts, frame } in encoded_frames {
+            let delay = timing.delay(end_pts, ordinal_frame_number)?;
+            enc.write_frame(frame, delay, &self.settings)?;
+
+            let mut reporter_lock = reporter.lock().map_err(|_| Error::ThreadSend)?;
+            let reporter = reporter_lock.as_deref_mut().ok_or(Error::Aborted)?;
+            // not written yet, but it's the size the file is going to have
+            reporter.written_bytes(enc.written_bytes());
+
+            // loop to report skipped frames too
+            while n_done < ordinal_frame_number {
+                n_done += 1;
+                if !reporter.increase() {
+                    *reporter_lock = None; // prevent further abort-caused errors from being logged
+                    return Err(Error::Aborted);
+                }
+            }
+        }
+        if n_done == 0 {
+            return Err(Error::NoFrames);
+        }
+        enc.finish(&self.settings)?;
+
+        if let Some(reporter) = reporter.lock().map_err(|_| Error::ThreadSend)?.as_deref_mut() {
+            reporter.written_bytes(enc.written_bytes());
+            reporter.timing(timing.report());
+        }
+        Ok(())
+    }
+}
+
+/// Moves `left` and `top` of the area to even coordinates, keeping its right and bottom edges
+fn align_to_even((left, top, width, height): (usize, usize, usize, usize)) -> (usize, usize, usize, usize) {
+    (left & !1, top & !1, width + (left & 1), height + (top & 1))
+}
+
+#[test]
+fn changed_area_is_even() {
+    let prev = ImgVec::new(vec![RGBA8::default(); 8 * 8], 8, 8);
+    let mut curr = prev.clone();
+    curr[(3_usize, 5_usize)] = RGBA8::new(1, 2, 3, 255);
+    curr[(6_usize, 5_usize)] = RGBA8::new(1, 2, 3, 255);
+    let bbox = changed_bbox(prev.as_ref(), curr.as_ref()).unwrap();
+    assert_eq!((3, 5, 4, 1), bbox);
+    assert_eq!((2, 4, 5, 2), align_to_even(bbox));
+    assert_eq!((2, 4, 1, 1), align_to_even((2, 4, 1, 1)));
+}
@@ -1,7 +1,7 @@
 use wasm_bindgen::prelude::*;
-use crate::{Settings, Repeat};
+use crate::{Settings, Repeat, Resize};
 use imgref::ImgVec;
-use rgb::RGBA8;
+use rgb::{RGB8, RGBA8};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -21,11 +21,11 @@ impl GifskiWasm {
         console_error_panic_hook::set_once();
         
         let settings = Settings {
-            width: if width > 0 { Some(width) } else { None },
-            height: if height > 0 { Some(height) } else { None },
+            resize: Resize::from_width_height((width > 0).then_some(width), (height > 0).then_some(height)),
             quality,
             fast: false,
             repeat: Repeat::Infinite,
+            ..Settings::default()
         };
         
         Ok(GifskiWasm { 
@@ -36,6 +36,25 @@ impl GifskiWasm {
         })
     }
     
+    /// 1-100. Lower values reduce motion
+    pub fn set_motion_quality(&mut self, quality: u8) {
+        self.settings.motion_quality = Some(quality);
+    }
+
+    /// 1-100. Lower values add noise. Only with the gifsicle feature
+    pub fn set_lossy_quality(&mut self, quality: u8) {
+        self.settings.lossy_quality = Some(quality);
+    }
+
+    pub fn set_extra_effort(&mut self, extra: bool) {
+        self.settings.extra_effort = extra;
+    }
+
+    /// Background color for semitransparent pixels
+    pub fn set_matte(&mut self, r: u8, g: u8, b: u8) {
+        self.settings.matte = Some(RGB8::new(r, g, b));
+    }
+    
     pub fn add_frame_rgba(&mut self, rgba_data: &[u8], width: u32, height: u32, timestamp: f64) -> Result<(), JsValue> {
         if rgba_data.len() != (width * height * 4) as usize {
             return Err(JsValue::from_str("Invalid RGBA data size"));
@@ -1,4 +1,4 @@
-use gifski::{new, progress, Settings};
+use gifski::{new, progress, FrameFit, Lossless, Resize, Settings, TimingMode};
 use imgref::{ImgRef, ImgRefMut, ImgVec};
 use rgb::{ComponentMap, RGBA8};
 use std::path::{Path, PathBuf};
@@ -11,7 +11,11 @@ fn n_frames() {
 }
 
 fn assert_anim_eq(num_frames: usize, frame_filename: fn(usize) -> PathBuf, frame_edit: Option<fn(usize, ImgRefMut<RGBA8>)>, max_diff: f64) {
-    let (c, w) = new(Settings::default()).unwrap();
+    assert_anim_eq_settings(Settings::default(), num_frames, frame_filename, frame_edit, max_diff);
+}
+
+fn assert_anim_eq_settings(settings: Settings, num_frames: usize, frame_filename: fn(usize) -> PathBuf, frame_edit: Option<fn(usize, ImgRefMut<RGBA8>)>, max_diff: f64) -> Vec<u8> {
+    let (c, w) = new(settings).unwrap();
 
     let t = std::thread::spawn(move || {
         for n in 0..num_frames {
@@ -49,6 +53,7 @@ fn assert_anim_eq(num_frames: usize, frame_filename: fn(usize) -> PathBuf, frame
         }
     });
     assert!(n == num_frames, "{frames_seen} : {num_frames}");
+    out
 }
 
 fn load_frame(name: &Path) -> ImgVec<RGBA8> {
@@ -154,6 +159,332 @@ fn all_but_one_dupe_frames() {
     assert_eq!(delays, [120, 20]);
 }
 
+#[test]
+fn similar_frames() {
+    let (c, w) = new(Settings::builder().merge_threshold(40).build().unwrap()).unwrap();
+
+    let t = std::thread::spawn(move || {
+        c.add_frame_png_file(0, frame_filename(0), 0.0).unwrap();
+        let mut frame = load_frame(&frame_filename(1));
+        c.add_frame_rgba(1, frame.clone(), 1.2).unwrap();
+        for (n, delta) in [30, 30].into_iter().enumerate() {
+            frame.pixels_mut().for_each(|px| px.g = px.g.saturating_add(delta));
+            c.add_frame_rgba(2 + n, frame.clone(), 1.3 + n as f64 / 10.).unwrap();
+        }
+    });
+
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut delays = vec![];
+    for_each_frame(&out, |_, frame, _| delays.push(frame.delay));
+    // the second change adds up to more than the threshold
+    assert_eq!(delays, [120, 20, 10]);
+}
+
+#[test]
+fn transient_overlay() {
+    let (c, w) = new(Settings::default()).unwrap();
+
+    let base = load_frame(&frame_filename(0));
+    let mut overlay = base.clone();
+    overlay.sub_image_mut(2, 2, 8, 4).pixels_mut().for_each(|px| *px = RGBA8::new(255, 0, 255, 255));
+    let frames = [base.clone(), overlay.clone(), base.clone(), overlay, base];
+    let expected = frames.clone();
+
+    let t = std::thread::spawn(move || {
+        for (n, frame) in frames.into_iter().enumerate() {
+            c.add_frame_rgba(n, frame, n as f64).unwrap();
+        }
+    });
+
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut n = 0;
+    for_each_frame(&out, |_, frame, actual| {
+        // the overlay is removed by disposing it, without redrawing the background
+        if n % 2 == 1 {
+            assert_eq!(gif::DisposalMethod::Previous, frame.dispose);
+        }
+        let diff = expected[n].pixels().zip(actual.pixels()).map(|(e, a)| u32::from(e.r.abs_diff(a.r)) + u32::from(e.g.abs_diff(a.g)) + u32::from(e.b.abs_diff(a.b))).max().unwrap();
+        assert!(diff < 80, "frame {n} differs by {diff}");
+        n += 1;
+    });
+    assert_eq!(5, n);
+}
+
+#[test]
+fn frame_durations() {
+    let (c, w) = new(Settings::default()).unwrap();
+
+    let t = std::thread::spawn(move || {
+        for (n, duration) in [0.05, 0.333, 0.07, 1.0].into_iter().enumerate() {
+            c.add_frame_rgba_with_duration(n, load_frame(&frame_filename(n)), duration).unwrap();
+        }
+    });
+
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut delays = vec![];
+    for_each_frame(&out, |_, frame, _| delays.push(frame.delay));
+    assert_eq!(delays, [5, 33, 7, 100]);
+
+    // the last frame has its own duration, not a guess from the frame rate
+    let (c, w) = new(Settings::default()).unwrap();
+    let t = std::thread::spawn(move || {
+        for (n, duration) in [0.1, 0.1, 2.5].into_iter().enumerate() {
+            c.add_frame_png_file_with_duration(n, frame_filename(n), duration).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut delays = vec![];
+    for_each_frame(&out, |_, frame, _| delays.push(frame.delay));
+    assert_eq!(delays, [10, 10, 250]);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn async_frame_durations() {
+    let (mut c, w) = gifski::asynchronous::new(Settings::default()).unwrap();
+    let mut out = Vec::new();
+    let mut pool = futures::executor::LocalPool::new();
+    pool.run_until(async {
+        let frames = async move {
+            for (n, duration) in [0.05, 0.2, 1.0].into_iter().enumerate() {
+                c.add_frame_rgba_with_duration(n, load_frame(&frame_filename(n)), duration).await.unwrap();
+            }
+        };
+        let ((), res) = futures::join!(frames, w.write(&mut out, progress::NoProgress {}));
+        res.unwrap();
+    });
+
+    let mut delays = vec![];
+    for_each_frame(&out, |_, frame, _| delays.push(frame.delay));
+    assert_eq!(delays, [5, 20, 100]);
+}
+
+#[test]
+fn timing_modes() {
+    struct Timing(Option<progress::TimingReport>);
+    impl progress::ProgressReporter for Timing {
+        fn increase(&mut self) -> bool { true }
+        fn timing(&mut self, report: &progress::TimingReport) {
+            self.0 = Some(report.clone());
+        }
+    }
+
+    fn encode(timing: TimingMode, fps: f64) -> (gifski::GifResult<()>, Vec<u8>, Option<progress::TimingReport>) {
+        let (c, w) = new(Settings::builder().timing(timing).build().unwrap()).unwrap();
+        let t = std::thread::spawn(move || {
+            for n in 0..12 {
+                if c.add_frame_png_file(n, frame_filename(n), n as f64 / fps).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut out = Vec::new();
+        let mut reporter = Timing(None);
+        let res = w.write(&mut out, &mut reporter);
+        t.join().unwrap();
+        (res, out, reporter.0)
+    }
+
+    let (res, _, report) = encode(TimingMode::Compensate, 60.);
+    res.unwrap();
+    let report = report.unwrap();
+    assert_eq!(12, report.frames);
+    assert!(report.final_drift > 0.03, "{report:?}");
+
+    let (res, out, report) = encode(TimingMode::Max50Fps, 60.);
+    res.unwrap();
+    let report = report.unwrap();
+    assert_eq!(2, report.merged_frames);
+    assert!(report.final_drift.abs() < 0.001, "{report:?}");
+    let mut frames = 0;
+    for_each_frame(&out, |_, frame, _| {
+        assert_eq!(0, frame.delay % 2);
+        frames += 1;
+    });
+    assert_eq!(10, frames);
+
+    let (res, _, _) = encode(TimingMode::Strict, 30.);
+    assert!(matches!(res, Err(gifski::Error::Timing(_))), "{res:?}");
+    let (res, _, report) = encode(TimingMode::Strict, 20.);
+    res.unwrap();
+    assert_eq!(0, report.unwrap().adjusted_frames);
+}
+
+#[test]
+fn max_size() {
+    fn encode(settings: Settings) -> Vec<u8> {
+        let (c, w) = new(settings).unwrap();
+        let t = std::thread::spawn(move || {
+            for n in 0..43 {
+                c.add_frame_png_file(n, format!("tests/a2/{:02}.png", 1 + n).into(), n as f64 / 10.).unwrap();
+            }
+        });
+        let mut out = Vec::new();
+        w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+        t.join().unwrap();
+        out
+    }
+
+    let unlimited = encode(Settings::default());
+    let max_size = unlimited.len() as u64 * 2 / 3;
+    let limited = encode(Settings::builder().max_size(max_size).build().unwrap());
+    assert!(limited.len() as u64 <= max_size, "{} > {max_size}", limited.len());
+
+    let mut frames = 0;
+    for_each_frame(&limited, |_, _, _| frames += 1);
+    assert!(frames > 1);
+}
+
+#[test]
+fn streaming() {
+    let mut enc = gifski::StreamingEncoder::new(Settings::default()).unwrap();
+    let mut out = Vec::new();
+    for n in 0..11 {
+        enc.push_frame(load_frame(&frame_filename(n)), n as f64 / 10.).unwrap();
+        enc.poll_output(&mut out);
+    }
+    out.extend(enc.finish().unwrap());
+
+    let mut n = 0;
+    for_each_frame(&out, |delay, _, actual| {
+        while n < delay as usize / 10 {
+            assert_images_eq(load_frame(&frame_filename(n)).as_ref(), actual, 0.8, format_args!("n={n}"));
+            n += 1;
+        }
+    });
+    assert_eq!(n, 11);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn async_write() {
+    let (mut c, w) = gifski::asynchronous::new(Settings::default()).unwrap();
+    let mut out = Vec::new();
+    let mut pool = futures::executor::LocalPool::new();
+    pool.run_until(async {
+        let frames = async move {
+            for n in 0..11 {
+                c.add_frame_png_file(n, frame_filename(n), n as f64 / 10.).await.unwrap();
+            }
+        };
+        let ((), res) = futures::join!(frames, w.write(&mut out, progress::NoProgress {}));
+        res.unwrap();
+    });
+
+    let mut frames_seen = 0;
+    for_each_frame(&out, |_, _, _| frames_seen += 1);
+    assert!(frames_seen > 1);
+}
+
+#[test]
+fn apng() {
+    for global_palette in [false, true] {
+        let (c, w) = new(Settings::builder().format(gifski::OutputFormat::Apng).global_palette(global_palette).build().unwrap()).unwrap();
+        let t = std::thread::spawn(move || {
+            for n in 0..3 {
+                c.add_frame_png_file(n, frame_filename(n), n as f64 / 10.).unwrap();
+            }
+        });
+        let mut out = Vec::new();
+        w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+        t.join().unwrap();
+
+        let mut decoder = apng::Decoder::new(&out[..]);
+        decoder.set_transformations(apng::Transformations::ALPHA);
+        let mut reader = decoder.read_info().unwrap();
+        assert_eq!(3, reader.info().animation_control.unwrap().num_frames);
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        let first = ImgVec::new(rgb::bytemuck::cast_slice::<u8, RGBA8>(&buf[..info.buffer_size()]).to_vec(), info.width as usize, info.height as usize);
+        // one palette for three different photos can't be as close as a palette for each
+        let max_diff = if global_palette { 7. } else { 0.8 };
+        assert_images_eq(load_frame(&frame_filename(0)).as_ref(), first.as_ref(), max_diff, format_args!("global_palette={global_palette}"));
+    }
+}
+
+#[test]
+fn semi_transparent() {
+    // a gradient of alpha, and the second frame changes only a corner
+    let first = ImgVec::new((0..32 * 32).map(|i| RGBA8::new(200, 100, 50, (i % 256) as u8)).collect::<Vec<_>>(), 32, 32);
+    let mut second = first.clone();
+    second[(0_usize, 0_usize)] = RGBA8::new(0, 0, 255, 255);
+
+    for format in [gifski::OutputFormat::Apng, gifski::OutputFormat::WebP] {
+        let (c, w) = new(Settings::builder().format(format).build().unwrap()).unwrap();
+        let frames = [first.clone(), second.clone()];
+        let t = std::thread::spawn(move || {
+            for (n, frame) in frames.into_iter().enumerate() {
+                c.add_frame_rgba(n, frame, n as f64).unwrap();
+            }
+        });
+        let mut out = Vec::new();
+        w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+        t.join().unwrap();
+
+        let mut decoded = Vec::new();
+        if format == gifski::OutputFormat::Apng {
+            let mut reader = apng::Decoder::new(&out[..]).read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buf).unwrap();
+            decoded.push(rgb::bytemuck::cast_slice::<u8, RGBA8>(&buf[..info.buffer_size()]).to_vec());
+        } else {
+            let mut decoder = image_webp::WebPDecoder::new(std::io::Cursor::new(out)).unwrap();
+            let mut buf = vec![0; decoder.output_buffer_size().unwrap()];
+            for _ in 0..decoder.num_frames() {
+                decoder.read_frame(&mut buf).unwrap();
+                decoded.push(rgb::bytemuck::cast_slice::<u8, RGBA8>(&buf).to_vec());
+            }
+            assert_eq!(decoded.len(), 2);
+        }
+        for (actual, expected) in decoded.iter().zip([&first, &second]) {
+            // colors of invisible pixels don't matter
+            let visible = |px: &RGBA8| if px.a > 0 { *px } else { RGBA8::default() };
+            assert_eq!(actual.iter().map(visible).collect::<Vec<_>>(), expected.pixels().map(|px| visible(&px)).collect::<Vec<_>>(), "{format:?}");
+        }
+    }
+}
+
+#[test]
+fn webp() {
+    let (c, w) = new(Settings::builder().format(gifski::OutputFormat::WebP).build().unwrap()).unwrap();
+    let t = std::thread::spawn(move || {
+        for n in 0..11 {
+            c.add_frame_png_file(n, frame_filename(n), n as f64 / 10.).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut decoder = image_webp::WebPDecoder::new(std::io::Cursor::new(out)).unwrap();
+    assert!(decoder.is_animated());
+    let (width, height) = decoder.dimensions();
+    let mut buf = vec![0; decoder.output_buffer_size().unwrap()];
+    let mut n = 0;
+    let mut time = 0;
+    for _ in 0..decoder.num_frames() {
+        time += decoder.read_frame(&mut buf).unwrap();
+        let actual = ImgVec::new(rgb::bytemuck::cast_slice::<u8, RGBA8>(&buf).to_vec(), width as usize, height as usize);
+        while n < time as usize / 100 {
+            assert_images_eq(load_frame(&frame_filename(n)).as_ref(), actual.as_ref(), 0.8, format_args!("n={n}"));
+            n += 1;
+        }
+    }
+    assert_eq!(n, 11);
+}
+
 fn frame_filename(n: usize) -> PathBuf {
     format!("tests/{}.png", (n % 3) + 1).into()
 }
@@ -172,11 +503,71 @@ fn for_each_frame(mut gif_data: &[u8], mut cb: impl FnMut(u32, &gif::Frame, ImgR
     }
 }
 
+/// Like `for_each_frame`, but images without delay are drawn as parts of the next frame. Returns how many there were.
+fn for_each_split_frame(mut gif_data: &[u8], mut cb: impl FnMut(&gif::Frame, ImgRef<RGBA8>)) -> usize {
+    let mut gif_opts = gif::DecodeOptions::new();
+    gif_opts.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = gif_opts.read_info(&mut gif_data).unwrap();
+    let mut screen = gif_dispose::Screen::new_decoder(&decoder);
+
+    let mut parts = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        screen.blit_frame(frame).unwrap();
+        if frame.delay == 0 {
+            parts += 1;
+            continue;
+        }
+        cb(frame, screen.pixels_rgba());
+    }
+    parts
+}
+
 #[test]
 fn anim3() {
     assert_anim_eq(6 * 3, |n| format!("tests/a3/{}{}.png", ["x", "y", "z"][n / 6], n % 6).into(), None, 0.8);
 }
 
+#[test]
+fn anim3_no_false_scene_cuts() {
+    // a square moving in a different way, but in the same scene, so nothing needs to be redrawn from scratch
+    let out = assert_anim_eq_settings(Settings::default(), 6 * 3, |n| format!("tests/a3/{}{}.png", ["x", "y", "z"][n / 6], n % 6).into(), None, 0.8);
+    let mut frames = 0;
+    for_each_frame(&out, |delay, frame, _| {
+        assert_ne!(gif::DisposalMethod::Background, frame.dispose, "frame ending at {delay}");
+        frames += 1;
+    });
+    assert_eq!(frames, 18);
+}
+
+#[test]
+fn scene_cut_keyframe() {
+    let (c, w) = new(Settings::default()).unwrap();
+    // the second scene shares only the bottom rows with the first one
+    let frames: Vec<_> = (0..8).map(|n| ImgVec::new((0..40 * 40).map(|i| {
+        let (x, y) = (i % 40, i / 40);
+        if (n * 4..n * 4 + 6).contains(&x) && (2..6).contains(&y) { RGBA8::new(255, 255, 255, 255) }
+        else if n >= 4 && y < 32 { RGBA8::new(250, 200, (x * 6) as u8, 255) }
+        else { RGBA8::new(10, 20, (y * 3) as u8, 255) }
+    }).collect::<Vec<_>>(), 40, 40)).collect();
+    let t = std::thread::spawn(move || {
+        for (n, frame) in frames.into_iter().enumerate() {
+            c.add_frame_rgba(n, frame, n as f64 / 10.).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut frames = Vec::new();
+    for_each_frame(&out, |_, frame, _| frames.push((frame.left, frame.top, frame.width, frame.height, frame.dispose)));
+    assert_eq!(frames.len(), 8);
+    // the previous scene is cleared, and the new one is drawn whole
+    assert_eq!(gif::DisposalMethod::Background, frames[3].4);
+    assert_eq!((0, 0, 40, 40), (frames[4].0, frames[4].1, frames[4].2, frames[4].3));
+    // the next frame within the scene only updates what has changed
+    assert!(frames[5].3 < 40);
+}
+
 #[test]
 fn anim3_transparent1() {
     assert_anim_eq(6*3, |n| format!("tests/a3/{}{}.png", ["x","y","z"][n/6], n%6).into(), Some(|_,mut fr| {
@@ -211,6 +602,229 @@ fn anim3_mix() {
     }), 2.);
 }
 
+#[test]
+fn anim3_interlaced() {
+    let settings = Settings::builder().interlaced(true).build().unwrap();
+    let out = assert_anim_eq_settings(settings, 6 * 3, |n| format!("tests/a3/{}{}.png", ["x", "y", "z"][n / 6], n % 6).into(), None, 0.8);
+    // decoded frames are deinterlaced, so only their headers have the flag
+    let mut decoder = gif::DecodeOptions::new().read_info(&out[..]).unwrap();
+    let mut n = 0;
+    while let Some(frame) = decoder.next_frame_info().unwrap() {
+        assert!(frame.interlaced);
+        n += 1;
+    }
+    assert_eq!(n, 18);
+}
+
+#[test]
+fn anim3_global_palette() {
+    let settings = Settings::builder().global_palette(true).build().unwrap();
+    let out = assert_anim_eq_settings(settings, 6 * 3, |n| format!("tests/a3/{}{}.png", ["x", "y", "z"][n / 6], n % 6).into(), None, 0.8);
+    for_each_frame(&out, |_, frame, _| {
+        assert!(frame.palette.is_none());
+    });
+}
+
+#[test]
+fn global_palette_few_colors() {
+    // the global palette has all colors of every frame
+    let (c, w) = new(Settings::builder().global_palette(true).quality(70).build().unwrap()).unwrap();
+    let colors: Vec<_> = (0..30).map(|i| RGBA8::new(i * 8, 255 - i * 5, i.wrapping_mul(77), 255)).collect();
+    let frames: Vec<_> = (0..3).map(|n| ImgVec::new((0..40 * 40).map(|i| colors[(i / 37 + n * i / 400) % 30]).collect::<Vec<_>>(), 40, 40)).collect();
+    let expected = frames.clone();
+    let t = std::thread::spawn(move || {
+        for (n, frame) in frames.into_iter().enumerate() {
+            c.add_frame_rgba(n, frame, n as f64).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+    let mut n = 0;
+    for_each_frame(&out, |_, _, actual| {
+        assert_eq!(expected[n].as_ref(), actual);
+        n += 1;
+    });
+    assert_eq!(n, 3);
+}
+
+#[test]
+fn reused_palette() {
+    let (c, w) = new(Settings::default()).unwrap();
+    // a square moving over a flat background needs no new colors after it has appeared
+    let frames: Vec<_> = (0..4).map(|n| ImgVec::new((0..40 * 40).map(|i| {
+        let (x, y) = (i % 40, i / 40);
+        if n > 0 && (n * 8..n * 8 + 10).contains(&x) && (10..20).contains(&y) { RGBA8::new(200, 20, 30, 255) } else { RGBA8::new(250, 250, 240, 255) }
+    }).collect::<Vec<_>>(), 40, 40)).collect();
+    let expected = frames.clone();
+    let t = std::thread::spawn(move || {
+        for (n, frame) in frames.into_iter().enumerate() {
+            c.add_frame_rgba(n, frame, n as f64).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut palettes = Vec::new();
+    for_each_frame(&out, |_, frame, actual| {
+        assert_eq!(expected[palettes.len()].as_ref(), actual);
+        palettes.push(frame.palette.clone());
+    });
+    assert_eq!(palettes.len(), 4);
+    // left out if it's the same as the first frame's, otherwise the same as the previous frame's
+    for pair in palettes[1..].windows(2) {
+        assert!(pair[1].is_none() || pair[1] == pair[0], "{pair:?}");
+    }
+}
+
+#[test]
+fn mixed_size_frames() {
+    // different colors, so that frames with the same shape aren't merged
+    let colors = [RGBA8::new(255, 0, 0, 255), RGBA8::new(0, 255, 0, 255), RGBA8::new(0, 0, 255, 255), RGBA8::new(255, 255, 0, 255)];
+    for fit in [FrameFit::Center, FrameFit::TopLeft, FrameFit::Scale] {
+        let settings = Settings::builder().frame_fit(fit).resize(Resize::Exact { width: 20, height: 20 }).build().unwrap();
+        let (c, w) = new(settings).unwrap();
+        // the canvas is 40×40, and the other frames are fitted to it before they're halved
+        let sizes = [(40, 40), (20, 20), (40, 20), (80, 20)];
+        let t = std::thread::spawn(move || {
+            for (n, (width, height)) in sizes.into_iter().enumerate() {
+                c.add_frame_rgba(n, ImgVec::new(vec![colors[n]; width * height], width, height), n as f64).unwrap();
+            }
+        });
+        let mut out = Vec::new();
+        w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+        t.join().unwrap();
+
+        let mut n = 0;
+        for_each_frame(&out, |_, _, actual| {
+            assert_eq!((20, 20), (actual.width(), actual.height()));
+            let opaque: Vec<Vec<bool>> = actual.rows().map(|row| row.iter().map(|px| px.a > 128).collect()).collect();
+            // (left, top, right, bottom) of the visible area
+            let expected = match (fit, n) {
+                (_, 0) => (0, 0, 20, 20),
+                (FrameFit::Center, 1) => (5, 5, 15, 15),
+                (FrameFit::TopLeft, 1) => (0, 0, 10, 10),
+                (FrameFit::Scale, 1) => (0, 0, 20, 20),
+                (FrameFit::Center | FrameFit::Scale, 2) => (0, 5, 20, 15),
+                (FrameFit::TopLeft, 2) => (0, 0, 20, 10),
+                (FrameFit::Center, 3) => (0, 5, 20, 15),
+                (FrameFit::TopLeft, 3) => (0, 0, 20, 10),
+                (FrameFit::Scale, 3) => (0, 8, 20, 13),
+                _ => unreachable!(),
+            };
+            for (y, row) in opaque.iter().enumerate() {
+                for (x, &is_opaque) in row.iter().enumerate() {
+                    let inside = (expected.0..expected.2).contains(&x) && (expected.1..expected.3).contains(&y);
+                    assert_eq!(inside, is_opaque, "{fit:?} frame {n} at {x},{y}");
+                }
+            }
+            n += 1;
+        });
+        assert_eq!(n, 4);
+    }
+}
+
+#[test]
+fn split_frames() {
+    let (c, w) = new(Settings::builder().split_frames(true).build().unwrap()).unwrap();
+    // changes in opposite corners, like two progress bars
+    let frames: Vec<_> = (0..4).map(|n| ImgVec::new((0..200 * 120).map(|i| {
+        let (x, y) = (i % 200, i / 200);
+        if (3..3 + n * 5).contains(&x) && (4..10).contains(&y) { RGBA8::new(20, 20, 200, 255) }
+        else if (180..190).contains(&x) && (110 - n * 5..110).contains(&y) { RGBA8::new(200, 20, 30, 255) }
+        else { RGBA8::new(250, 250, 240, 255) }
+    }).collect::<Vec<_>>(), 200, 120)).collect();
+    let expected = frames.clone();
+    let t = std::thread::spawn(move || {
+        for (n, frame) in frames.into_iter().enumerate() {
+            c.add_frame_rgba(n, frame, n as f64).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut n = 0;
+    let parts = for_each_split_frame(&out, |frame, actual| {
+        assert_eq!(expected[n].as_ref(), actual);
+        if n < 3 {
+            assert_eq!(frame.delay, 100);
+        }
+        n += 1;
+    });
+    assert_eq!(n, 4);
+    assert!(parts > 0);
+}
+
+#[test]
+fn high_color() {
+    let (c, w) = new(Settings::builder().high_color(true).build().unwrap()).unwrap();
+
+    let t = std::thread::spawn(move || {
+        let gradient = ImgVec::new((0..256 * 256).map(|i| RGBA8::new((i % 256) as u8, (i / 256) as u8, (i % 97) as u8, 255)).collect(), 256, 256);
+        c.add_frame_rgba(0, gradient.clone(), 0.).unwrap();
+        c.add_frame_rgba(1, gradient, 1.).unwrap();
+    });
+
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+
+    let mut colors = vec![];
+    for_each_frame(&out, |_, _, actual| {
+        if colors.is_empty() {
+            colors = actual.pixels().collect();
+        }
+    });
+    colors.sort_unstable_by_key(|px| (px.r, px.g, px.b));
+    colors.dedup();
+    assert!(colors.len() > 256, "{}", colors.len());
+}
+
+#[test]
+fn lossless() {
+    for global_palette in [false, true] {
+        let settings = Settings::builder().lossless(Lossless::Required).global_palette(global_palette).build().unwrap();
+        assert_anim_eq_settings(settings, 6 * 3, |n| format!("tests/a3/{}{}.png", ["x", "y", "z"][n / 6], n % 6).into(), Some(|_, mut fr| {
+            // 64 colors
+            fr.pixels_mut().for_each(|px| *px = RGBA8::new(px.r & 0xC0, px.g & 0xC0, px.b & 0xC0, if px.a < 128 { 0 } else { 255 }));
+        }), 0.);
+    }
+
+    let (c, w) = new(Settings::builder().lossless(Lossless::Required).build().unwrap()).unwrap();
+    let t = std::thread::spawn(move || {
+        let gradient = (0..20 * 20).map(|i: u32| RGBA8::new(i as u8, (i >> 8) as u8, 0, 255)).collect();
+        let _ = c.add_frame_rgba(0, ImgVec::new(gradient, 20, 20), 0.);
+    });
+    let mut out = Vec::new();
+    let res = w.write(&mut out, &mut progress::NoProgress {});
+    t.join().unwrap();
+    assert!(matches!(res, Err(gifski::Error::TooManyColors(1))), "{res:?}");
+
+    // all 256 colors change, so there's no room for the transparent color for the last row that doesn't
+    let (c, w) = new(Settings::builder().lossless(Lossless::Required).build().unwrap()).unwrap();
+    let first = ImgVec::new((0..16 * 17).map(|i: u32| RGBA8::new(i as u8, 255 - i as u8, (i * 7) as u8, 255)).collect::<Vec<_>>(), 16, 17);
+    let mut second = first.clone();
+    second.buf_mut()[..256].rotate_left(1);
+    let frames = [first, second];
+    let expected = frames.clone();
+    let t = std::thread::spawn(move || {
+        for (n, frame) in frames.into_iter().enumerate() {
+            c.add_frame_rgba(n, frame, n as f64).unwrap();
+        }
+    });
+    let mut out = Vec::new();
+    w.write(&mut out, &mut progress::NoProgress {}).unwrap();
+    t.join().unwrap();
+    let mut n = 0;
+    for_each_frame(&out, |_, _, actual| {
+        assert_images_eq(expected[n].as_ref(), actual, 0., format_args!("n={n}"));
+        n += 1;
+    });
+    assert_eq!(2, n);
+}
+
 #[test]
 fn anim2_fwd() {
     assert_anim_eq(43, |n| format!("tests/a2/{:02}.png", 1 + n).into(), None, 0.8);